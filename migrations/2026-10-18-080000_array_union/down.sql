@@ -0,0 +1,2 @@
+-- Drop the array union function
+DROP FUNCTION IF EXISTS array_union(TEXT[], TEXT[]);
@@ -0,0 +1,10 @@
+-- Union of two text arrays, keeping each element at its first position
+CREATE OR REPLACE FUNCTION array_union(a TEXT[], b TEXT[])
+RETURNS TEXT[] AS $$
+    SELECT COALESCE(array_agg(elem ORDER BY pos), '{}')
+    FROM (
+        SELECT elem, MIN(pos) AS pos
+        FROM unnest(a || b) WITH ORDINALITY AS merged(elem, pos)
+        GROUP BY elem
+    ) AS deduplicated;
+$$ LANGUAGE SQL IMMUTABLE;
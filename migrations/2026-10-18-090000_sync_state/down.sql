@@ -0,0 +1,5 @@
+-- Drop indexes first
+DROP INDEX IF EXISTS idx_sync_state_pipeline_status;
+
+-- Drop the 'sync_state' table
+DROP TABLE IF EXISTS sync_state;
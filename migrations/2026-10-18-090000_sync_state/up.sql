@@ -0,0 +1,14 @@
+-- Create the 'sync_state' table tracking each item of a sync pipeline
+CREATE TABLE IF NOT EXISTS sync_state (
+    pipeline    VARCHAR(50) NOT NULL,
+    item_key    VARCHAR(500) NOT NULL,
+    status      VARCHAR(20) NOT NULL DEFAULT 'pending'
+                CHECK (status IN ('pending', 'done', 'failed')),
+    attempts    INT NOT NULL DEFAULT 0,
+    last_error  TEXT,
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (pipeline, item_key)
+);
+
+-- Create an index on the 'pipeline' and 'status' columns of the 'sync_state' table
+CREATE INDEX IF NOT EXISTS idx_sync_state_pipeline_status ON sync_state (pipeline, status);
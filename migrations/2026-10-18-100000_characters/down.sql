@@ -0,0 +1,12 @@
+-- Drop indexes first
+DROP INDEX IF EXISTS idx_anime_characters_character_id;
+DROP INDEX IF EXISTS idx_voice_actors_staff_id;
+
+-- Drop the 'voice_actors' table
+DROP TABLE IF EXISTS voice_actors;
+
+-- Drop the 'anime_characters' table
+DROP TABLE IF EXISTS anime_characters;
+
+-- Drop the 'characters' table
+DROP TABLE IF EXISTS characters;
@@ -0,0 +1,34 @@
+-- Create the 'characters' table if it does not exist
+CREATE TABLE IF NOT EXISTS characters (
+    mal_id      INT PRIMARY KEY,
+    name        VARCHAR(255) NOT NULL,
+    mal_url     VARCHAR(500) NOT NULL,
+    image       VARCHAR(200) NOT NULL
+);
+
+-- Create the 'anime_characters' table if it does not exist
+CREATE TABLE IF NOT EXISTS anime_characters (
+    anime_id      INT NOT NULL,
+    character_id  INT NOT NULL,
+    role          VARCHAR(50) NOT NULL,
+    PRIMARY KEY (anime_id, character_id),
+    FOREIGN KEY (anime_id) REFERENCES anime(id) ON DELETE CASCADE,
+    FOREIGN KEY (character_id) REFERENCES characters(mal_id) ON DELETE CASCADE
+);
+
+-- Create the 'voice_actors' table if it does not exist, voice actors are rows of 'staff'
+CREATE TABLE IF NOT EXISTS voice_actors (
+    anime_id      INT NOT NULL,
+    character_id  INT NOT NULL,
+    staff_id      INT NOT NULL,
+    language      VARCHAR(50) NOT NULL,
+    PRIMARY KEY (anime_id, character_id, staff_id),
+    FOREIGN KEY (anime_id, character_id) REFERENCES anime_characters(anime_id, character_id) ON DELETE CASCADE,
+    FOREIGN KEY (staff_id) REFERENCES staff(mal_id) ON DELETE CASCADE
+);
+
+-- Create an index on the 'character_id' column of the 'anime_characters' table
+CREATE INDEX IF NOT EXISTS idx_anime_characters_character_id ON anime_characters (character_id);
+
+-- Create an index on the 'staff_id' column of the 'voice_actors' table
+CREATE INDEX IF NOT EXISTS idx_voice_actors_staff_id ON voice_actors (staff_id);
@@ -0,0 +1,5 @@
+-- Drop indexes first
+DROP INDEX IF EXISTS idx_anime_relations_related_mal_id;
+
+-- Drop the 'anime_relations' table
+DROP TABLE IF EXISTS anime_relations;
@@ -0,0 +1,12 @@
+-- Create the 'anime_relations' table if it does not exist. The related anime is kept by
+-- MAL ID, it maps to the local anime through 'anime.mal_id' once that anime is stored
+CREATE TABLE IF NOT EXISTS anime_relations (
+    anime_id        INT NOT NULL,
+    related_mal_id  INT NOT NULL,
+    relation        VARCHAR(50) NOT NULL,
+    PRIMARY KEY (anime_id, related_mal_id),
+    FOREIGN KEY (anime_id) REFERENCES anime(id) ON DELETE CASCADE
+);
+
+-- Create an index on the 'related_mal_id' column of the 'anime_relations' table
+CREATE INDEX IF NOT EXISTS idx_anime_relations_related_mal_id ON anime_relations (related_mal_id);
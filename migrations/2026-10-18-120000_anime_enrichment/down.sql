@@ -0,0 +1,8 @@
+-- Drop indexes first
+DROP INDEX IF EXISTS idx_anime_enrichment_al_id;
+
+-- Drop the 'anime_enrichment_tags' table
+DROP TABLE IF EXISTS anime_enrichment_tags;
+
+-- Drop the 'anime_enrichment' table
+DROP TABLE IF EXISTS anime_enrichment;
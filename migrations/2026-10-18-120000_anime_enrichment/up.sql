@@ -0,0 +1,27 @@
+-- Create the 'anime_enrichment' table holding the fields fetched from AniList by 'al_id'
+CREATE TABLE IF NOT EXISTS anime_enrichment (
+    anime_id        INT PRIMARY KEY,
+    al_id           INT NOT NULL,
+    cover_color     VARCHAR(20),
+    banner_image    VARCHAR(500),
+    start_date      DATE,
+    end_date        DATE,
+    season          VARCHAR(20),
+    season_year     INT,
+    popularity      INT,
+    average_score   INT,
+    fetched_at      TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (anime_id) REFERENCES anime(id) ON DELETE CASCADE
+);
+
+-- Create the 'anime_enrichment_tags' table if it does not exist
+CREATE TABLE IF NOT EXISTS anime_enrichment_tags (
+    anime_id    INT NOT NULL,
+    name        VARCHAR(100) NOT NULL,
+    rank        INT,
+    PRIMARY KEY (anime_id, name),
+    FOREIGN KEY (anime_id) REFERENCES anime_enrichment(anime_id) ON DELETE CASCADE
+);
+
+-- Create an index on the 'al_id' column of the 'anime_enrichment' table
+CREATE INDEX IF NOT EXISTS idx_anime_enrichment_al_id ON anime_enrichment (al_id);
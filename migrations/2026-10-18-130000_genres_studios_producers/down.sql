@@ -0,0 +1,22 @@
+-- Drop indexes first
+DROP INDEX IF EXISTS idx_anime_genres_genre_id;
+DROP INDEX IF EXISTS idx_anime_studios_studio_id;
+DROP INDEX IF EXISTS idx_anime_producers_producer_id;
+
+-- Drop the 'anime_genres' table
+DROP TABLE IF EXISTS anime_genres;
+
+-- Drop the 'genres' table
+DROP TABLE IF EXISTS genres;
+
+-- Drop the 'anime_studios' table
+DROP TABLE IF EXISTS anime_studios;
+
+-- Drop the 'studios' table
+DROP TABLE IF EXISTS studios;
+
+-- Drop the 'anime_producers' table
+DROP TABLE IF EXISTS anime_producers;
+
+-- Drop the 'producers' table
+DROP TABLE IF EXISTS producers;
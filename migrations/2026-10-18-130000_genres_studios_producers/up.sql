@@ -0,0 +1,92 @@
+-- Create the 'genres' table if it does not exist
+CREATE TABLE IF NOT EXISTS genres (
+    id      SERIAL PRIMARY KEY,
+    name    VARCHAR(255) UNIQUE NOT NULL
+);
+
+-- Create the 'anime_genres' table if it does not exist
+CREATE TABLE IF NOT EXISTS anime_genres (
+    anime_id    INT NOT NULL,
+    genre_id    INT NOT NULL,
+    PRIMARY KEY (anime_id, genre_id),
+    FOREIGN KEY (anime_id) REFERENCES anime(id) ON DELETE CASCADE,
+    FOREIGN KEY (genre_id) REFERENCES genres(id) ON DELETE CASCADE
+);
+
+-- Create an index on the 'genre_id' column of the 'anime_genres' table
+CREATE INDEX IF NOT EXISTS idx_anime_genres_genre_id ON anime_genres (genre_id);
+
+-- Backfill from the comma separated 'anime.genres' column
+INSERT INTO genres (name)
+SELECT DISTINCT TRIM(split.name)
+FROM anime CROSS JOIN LATERAL UNNEST(STRING_TO_ARRAY(anime.genres, ',')) AS split(name)
+WHERE TRIM(split.name) <> ''
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO anime_genres (anime_id, genre_id)
+SELECT DISTINCT anime.id, genres.id
+FROM anime CROSS JOIN LATERAL UNNEST(STRING_TO_ARRAY(anime.genres, ',')) AS split(name)
+JOIN genres ON genres.name = TRIM(split.name)
+ON CONFLICT DO NOTHING;
+
+-- Create the 'studios' table if it does not exist
+CREATE TABLE IF NOT EXISTS studios (
+    id      SERIAL PRIMARY KEY,
+    name    VARCHAR(255) UNIQUE NOT NULL
+);
+
+-- Create the 'anime_studios' table if it does not exist
+CREATE TABLE IF NOT EXISTS anime_studios (
+    anime_id    INT NOT NULL,
+    studio_id   INT NOT NULL,
+    PRIMARY KEY (anime_id, studio_id),
+    FOREIGN KEY (anime_id) REFERENCES anime(id) ON DELETE CASCADE,
+    FOREIGN KEY (studio_id) REFERENCES studios(id) ON DELETE CASCADE
+);
+
+-- Create an index on the 'studio_id' column of the 'anime_studios' table
+CREATE INDEX IF NOT EXISTS idx_anime_studios_studio_id ON anime_studios (studio_id);
+
+-- Backfill from the comma separated 'anime.studios' column
+INSERT INTO studios (name)
+SELECT DISTINCT TRIM(split.name)
+FROM anime CROSS JOIN LATERAL UNNEST(STRING_TO_ARRAY(anime.studios, ',')) AS split(name)
+WHERE TRIM(split.name) <> ''
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO anime_studios (anime_id, studio_id)
+SELECT DISTINCT anime.id, studios.id
+FROM anime CROSS JOIN LATERAL UNNEST(STRING_TO_ARRAY(anime.studios, ',')) AS split(name)
+JOIN studios ON studios.name = TRIM(split.name)
+ON CONFLICT DO NOTHING;
+
+-- Create the 'producers' table if it does not exist
+CREATE TABLE IF NOT EXISTS producers (
+    id      SERIAL PRIMARY KEY,
+    name    VARCHAR(255) UNIQUE NOT NULL
+);
+
+-- Create the 'anime_producers' table if it does not exist
+CREATE TABLE IF NOT EXISTS anime_producers (
+    anime_id    INT NOT NULL,
+    producer_id INT NOT NULL,
+    PRIMARY KEY (anime_id, producer_id),
+    FOREIGN KEY (anime_id) REFERENCES anime(id) ON DELETE CASCADE,
+    FOREIGN KEY (producer_id) REFERENCES producers(id) ON DELETE CASCADE
+);
+
+-- Create an index on the 'producer_id' column of the 'anime_producers' table
+CREATE INDEX IF NOT EXISTS idx_anime_producers_producer_id ON anime_producers (producer_id);
+
+-- Backfill from the comma separated 'anime.producers' column
+INSERT INTO producers (name)
+SELECT DISTINCT TRIM(split.name)
+FROM anime CROSS JOIN LATERAL UNNEST(STRING_TO_ARRAY(anime.producers, ',')) AS split(name)
+WHERE TRIM(split.name) <> ''
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO anime_producers (anime_id, producer_id)
+SELECT DISTINCT anime.id, producers.id
+FROM anime CROSS JOIN LATERAL UNNEST(STRING_TO_ARRAY(anime.producers, ',')) AS split(name)
+JOIN producers ON producers.name = TRIM(split.name)
+ON CONFLICT DO NOTHING;
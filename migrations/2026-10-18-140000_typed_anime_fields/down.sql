@@ -0,0 +1,36 @@
+-- Restore empty strings for a missing duration, premiere or aired range
+UPDATE anime SET
+    duration = COALESCE(duration, ''),
+    premiered = COALESCE(premiered, ''),
+    aired = COALESCE(aired, '');
+
+ALTER TABLE anime
+    ALTER COLUMN duration SET NOT NULL,
+    ALTER COLUMN premiered SET NOT NULL,
+    ALTER COLUMN aired SET NOT NULL;
+
+-- Convert 'mal_score' back to text
+ALTER TABLE anime
+    ALTER COLUMN mal_score TYPE VARCHAR(50) USING COALESCE(mal_score::TEXT, 'n/a'),
+    ALTER COLUMN mal_score SET NOT NULL;
+
+-- Restore empty strings for a missing category, rating, status or audio
+UPDATE anime SET
+    category = COALESCE(category, ''),
+    rating = COALESCE(rating, ''),
+    status = COALESCE(status, ''),
+    sub_or_dub = COALESCE(sub_or_dub, '');
+
+ALTER TABLE anime
+    ALTER COLUMN category SET NOT NULL,
+    ALTER COLUMN rating SET NOT NULL,
+    ALTER COLUMN status SET NOT NULL,
+    ALTER COLUMN sub_or_dub SET NOT NULL;
+
+-- Drop the parsed columns
+ALTER TABLE anime
+    DROP COLUMN IF EXISTS duration_minutes,
+    DROP COLUMN IF EXISTS premiered_season,
+    DROP COLUMN IF EXISTS premiered_year,
+    DROP COLUMN IF EXISTS aired_start,
+    DROP COLUMN IF EXISTS aired_end;
@@ -0,0 +1,61 @@
+-- Add the columns parsed from 'duration', 'premiered' and 'aired'
+ALTER TABLE anime
+    ADD COLUMN IF NOT EXISTS duration_minutes INT,
+    ADD COLUMN IF NOT EXISTS premiered_season VARCHAR(50),
+    ADD COLUMN IF NOT EXISTS premiered_year INT,
+    ADD COLUMN IF NOT EXISTS aired_start DATE,
+    ADD COLUMN IF NOT EXISTS aired_end DATE;
+
+-- Backfill them from the text columns, leaving values that do not parse NULL
+UPDATE anime SET
+    duration_minutes = CASE
+        WHEN duration ~ '^\d+[hm]( \d+[hm])*$' THEN
+            COALESCE(substring(duration FROM '(\d+)h')::INT, 0) * 60
+                + COALESCE(substring(duration FROM '(\d+)m')::INT, 0)
+    END,
+    premiered_season = CASE
+        WHEN premiered ~ '^\S+ \d{4}$' THEN split_part(premiered, ' ', 1)
+    END,
+    premiered_year = CASE
+        WHEN premiered ~ '^\S+ \d{4}$' THEN split_part(premiered, ' ', 2)::INT
+    END,
+    aired_start = CASE
+        WHEN split_part(aired, ' to ', 1) ~ '^[A-Z][a-z]{2} \d{1,2}, \d{4}$'
+            THEN to_date(split_part(aired, ' to ', 1), 'Mon DD, YYYY')
+    END,
+    aired_end = CASE
+        WHEN split_part(aired, ' to ', 2) ~ '^[A-Z][a-z]{2} \d{1,2}, \d{4}$'
+            THEN to_date(split_part(aired, ' to ', 2), 'Mon DD, YYYY')
+    END;
+
+-- Store a missing category, rating, status or audio as NULL rather than an empty string
+ALTER TABLE anime
+    ALTER COLUMN category DROP NOT NULL,
+    ALTER COLUMN rating DROP NOT NULL,
+    ALTER COLUMN status DROP NOT NULL,
+    ALTER COLUMN sub_or_dub DROP NOT NULL;
+
+UPDATE anime SET
+    category = NULLIF(category, ''),
+    rating = NULLIF(rating, ''),
+    status = NULLIF(status, ''),
+    sub_or_dub = NULLIF(sub_or_dub, '');
+
+-- Convert 'mal_score' to a number, placeholders such as 'n/a' become NULL
+ALTER TABLE anime
+    ALTER COLUMN mal_score DROP NOT NULL,
+    ALTER COLUMN mal_score TYPE REAL USING CASE
+        WHEN mal_score ~ '^\d+(\.\d+)?$' THEN mal_score::REAL
+    END;
+
+-- Keep the text columns as fetched next to the parsed ones, so values that do not
+-- parse are not lost, storing a missing value as NULL
+ALTER TABLE anime
+    ALTER COLUMN duration DROP NOT NULL,
+    ALTER COLUMN premiered DROP NOT NULL,
+    ALTER COLUMN aired DROP NOT NULL;
+
+UPDATE anime SET
+    duration = NULLIF(duration, ''),
+    premiered = NULLIF(premiered, ''),
+    aired = NULLIF(aired, '');
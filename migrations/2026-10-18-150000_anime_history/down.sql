@@ -0,0 +1,12 @@
+-- Drop indexes first
+DROP INDEX IF EXISTS idx_episode_history_anime_id;
+DROP INDEX IF EXISTS idx_anime_history_anime_id;
+
+-- Drop the 'episode_history' table
+DROP TABLE IF EXISTS episode_history;
+
+-- Drop the 'anime_history' table
+DROP TABLE IF EXISTS anime_history;
+
+-- Drop the sync run sequence
+DROP SEQUENCE IF EXISTS sync_run_id_seq;
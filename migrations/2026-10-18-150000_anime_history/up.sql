@@ -0,0 +1,34 @@
+-- Create the sequence numbering sync runs if it does not exist
+CREATE SEQUENCE IF NOT EXISTS sync_run_id_seq;
+
+-- Create the 'anime_history' table holding one row per changed field of an anime
+CREATE TABLE IF NOT EXISTS anime_history (
+    id           BIGSERIAL PRIMARY KEY,
+    anime_id     INT NOT NULL,
+    sync_run_id  BIGINT,
+    field        VARCHAR(100) NOT NULL,
+    old_value    TEXT,
+    new_value    TEXT,
+    changed_at   TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (anime_id) REFERENCES anime(id) ON DELETE CASCADE
+);
+
+-- Create an index on the 'anime_id' and 'changed_at' columns of the 'anime_history' table
+CREATE INDEX IF NOT EXISTS idx_anime_history_anime_id ON anime_history (anime_id, changed_at);
+
+-- Create the 'episode_history' table holding one row per changed field of an episode
+CREATE TABLE IF NOT EXISTS episode_history (
+    id           BIGSERIAL PRIMARY KEY,
+    episode_id   VARCHAR(500) NOT NULL,
+    anime_id     INT NOT NULL,
+    sync_run_id  BIGINT,
+    field        VARCHAR(100) NOT NULL,
+    old_value    TEXT,
+    new_value    TEXT,
+    changed_at   TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    FOREIGN KEY (episode_id) REFERENCES episodes(id) ON DELETE CASCADE,
+    FOREIGN KEY (anime_id) REFERENCES anime(id) ON DELETE CASCADE
+);
+
+-- Create an index on the 'anime_id' and 'changed_at' columns of the 'episode_history' table
+CREATE INDEX IF NOT EXISTS idx_episode_history_anime_id ON episode_history (anime_id, changed_at);
@@ -0,0 +1,12 @@
+-- Drop indexes first
+DROP INDEX IF EXISTS idx_sync_run_errors_sync_run_id;
+DROP INDEX IF EXISTS idx_sync_runs_started_at;
+
+-- Drop the 'sync_run_errors' table
+DROP TABLE IF EXISTS sync_run_errors;
+
+-- Drop the 'sync_run_counts' table
+DROP TABLE IF EXISTS sync_run_counts;
+
+-- Drop the 'sync_runs' table
+DROP TABLE IF EXISTS sync_runs;
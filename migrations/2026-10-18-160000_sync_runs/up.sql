@@ -0,0 +1,38 @@
+-- Create the 'sync_runs' table, numbered by the sequence the history tables already use
+CREATE TABLE IF NOT EXISTS sync_runs (
+    id           BIGINT PRIMARY KEY DEFAULT nextval('sync_run_id_seq'),
+    command      TEXT NOT NULL,
+    status       VARCHAR(20) NOT NULL DEFAULT 'running'
+                 CHECK (status IN ('running', 'succeeded', 'failed', 'cancelled')),
+    error        TEXT,
+    started_at   TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    finished_at  TIMESTAMPTZ
+);
+
+-- Create an index on the 'started_at' column of the 'sync_runs' table
+CREATE INDEX IF NOT EXISTS idx_sync_runs_started_at ON sync_runs (started_at);
+
+-- Create the 'sync_run_counts' table holding what a run did to each entity
+CREATE TABLE IF NOT EXISTS sync_run_counts (
+    sync_run_id  BIGINT NOT NULL,
+    entity       VARCHAR(50) NOT NULL,
+    inserted     BIGINT NOT NULL DEFAULT 0,
+    updated      BIGINT NOT NULL DEFAULT 0,
+    unchanged    BIGINT NOT NULL DEFAULT 0,
+    failed       BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (sync_run_id, entity),
+    FOREIGN KEY (sync_run_id) REFERENCES sync_runs(id) ON DELETE CASCADE
+);
+
+-- Create the 'sync_run_errors' table holding a sample of the errors of a run
+CREATE TABLE IF NOT EXISTS sync_run_errors (
+    id           BIGSERIAL PRIMARY KEY,
+    sync_run_id  BIGINT NOT NULL,
+    entity       VARCHAR(50) NOT NULL,
+    item_key     VARCHAR(500) NOT NULL,
+    message      TEXT NOT NULL,
+    FOREIGN KEY (sync_run_id) REFERENCES sync_runs(id) ON DELETE CASCADE
+);
+
+-- Create an index on the 'sync_run_id' column of the 'sync_run_errors' table
+CREATE INDEX IF NOT EXISTS idx_sync_run_errors_sync_run_id ON sync_run_errors (sync_run_id);
@@ -0,0 +1,30 @@
+-- Drop indexes first
+DROP INDEX IF EXISTS idx_staff_last_fetched_at;
+DROP INDEX IF EXISTS idx_anime_id_last_fetched_at;
+DROP INDEX IF EXISTS idx_anime_last_fetched_at;
+
+-- Drop the timestamp columns
+ALTER TABLE anime_staff
+    DROP COLUMN IF EXISTS last_fetched_at,
+    DROP COLUMN IF EXISTS updated_at,
+    DROP COLUMN IF EXISTS created_at;
+
+ALTER TABLE staff
+    DROP COLUMN IF EXISTS last_fetched_at,
+    DROP COLUMN IF EXISTS updated_at,
+    DROP COLUMN IF EXISTS created_at;
+
+ALTER TABLE episodes
+    DROP COLUMN IF EXISTS last_fetched_at,
+    DROP COLUMN IF EXISTS updated_at,
+    DROP COLUMN IF EXISTS created_at;
+
+ALTER TABLE anime_id
+    DROP COLUMN IF EXISTS last_fetched_at,
+    DROP COLUMN IF EXISTS updated_at,
+    DROP COLUMN IF EXISTS created_at;
+
+ALTER TABLE anime
+    DROP COLUMN IF EXISTS last_fetched_at,
+    DROP COLUMN IF EXISTS updated_at,
+    DROP COLUMN IF EXISTS created_at;
@@ -0,0 +1,46 @@
+-- Add 'created_at', 'updated_at' and 'last_fetched_at' to every table of the anime
+-- migration. Rows that exist already are created and updated at the time of this migration
+ALTER TABLE anime
+    ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN IF NOT EXISTS last_fetched_at TIMESTAMPTZ;
+
+ALTER TABLE anime_id
+    ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN IF NOT EXISTS last_fetched_at TIMESTAMPTZ;
+
+ALTER TABLE episodes
+    ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN IF NOT EXISTS last_fetched_at TIMESTAMPTZ;
+
+ALTER TABLE staff
+    ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN IF NOT EXISTS last_fetched_at TIMESTAMPTZ;
+
+ALTER TABLE anime_staff
+    ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN IF NOT EXISTS last_fetched_at TIMESTAMPTZ;
+
+-- When rows were last fetched is unknown, so 'last_fetched_at' stays NULL for them and
+-- they count as stale. Rows inserted from now on are fetched as they are inserted
+ALTER TABLE anime ALTER COLUMN last_fetched_at SET DEFAULT NOW();
+ALTER TABLE episodes ALTER COLUMN last_fetched_at SET DEFAULT NOW();
+ALTER TABLE staff ALTER COLUMN last_fetched_at SET DEFAULT NOW();
+ALTER TABLE anime_staff ALTER COLUMN last_fetched_at SET DEFAULT NOW();
+
+-- An anime name is fetched by the details pipeline, not when the A-Z list adds it, so
+-- take the time of its last successful fetch from the checkpoints
+UPDATE anime_id SET last_fetched_at = sync_state.updated_at
+FROM sync_state
+WHERE sync_state.pipeline = 'details'
+    AND sync_state.status = 'done'
+    AND sync_state.item_key = anime_id.anime_name;
+
+-- Create indexes on the 'last_fetched_at' columns used to select stale rows
+CREATE INDEX IF NOT EXISTS idx_anime_last_fetched_at ON anime (last_fetched_at);
+CREATE INDEX IF NOT EXISTS idx_anime_id_last_fetched_at ON anime_id (last_fetched_at);
+CREATE INDEX IF NOT EXISTS idx_staff_last_fetched_at ON staff (last_fetched_at);
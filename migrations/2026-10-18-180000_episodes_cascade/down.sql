@@ -0,0 +1,5 @@
+-- Restore the foreign key without the cascade
+ALTER TABLE episodes
+    DROP CONSTRAINT IF EXISTS episodes_anime_id_fkey,
+    ADD CONSTRAINT episodes_anime_id_fkey
+        FOREIGN KEY (anime_id) REFERENCES anime(id);
@@ -0,0 +1,5 @@
+-- Delete the episodes of an anime along with it, like every other table referencing anime
+ALTER TABLE episodes
+    DROP CONSTRAINT IF EXISTS episodes_anime_id_fkey,
+    ADD CONSTRAINT episodes_anime_id_fkey
+        FOREIGN KEY (anime_id) REFERENCES anime(id) ON DELETE CASCADE;
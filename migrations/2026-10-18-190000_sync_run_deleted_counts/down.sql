@@ -0,0 +1,3 @@
+-- Drop the 'deleted' column of 'sync_run_counts'
+ALTER TABLE sync_run_counts
+    DROP COLUMN IF EXISTS deleted;
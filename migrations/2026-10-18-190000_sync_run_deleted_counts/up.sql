@@ -0,0 +1,4 @@
+-- Add 'deleted' to 'sync_run_counts', the rows a run removed because they were gone from
+-- the source, such as relations dropped on a re-sync
+ALTER TABLE sync_run_counts
+    ADD COLUMN IF NOT EXISTS deleted BIGINT NOT NULL DEFAULT 0;
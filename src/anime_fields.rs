@@ -0,0 +1,167 @@
+// anime_fields.rs
+
+use chrono::NaiveDate;
+use diesel::deserialize::{self, FromSql, FromSqlRow};
+use diesel::expression::AsExpression;
+use diesel::pg::{Pg, PgValue};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Text;
+use std::fmt;
+
+// Format of the dates in the `aired` field, e.g. `Oct 3, 2020`
+const AIRED_DATE_FORMAT: &str = "%b %d, %Y";
+
+// Declare an enum stored as its text value. Values this version does not know are kept
+// in `Other` as fetched, so no information is lost when the site adds a new one
+macro_rules! text_enum {
+    ($(#[$meta:meta])* $name:ident { $($variant:ident => $text:literal,)+ }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq, AsExpression, FromSqlRow)]
+        #[diesel(sql_type = Text)]
+        pub enum $name {
+            $($variant,)+
+            Other(String),
+        }
+
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $($name::$variant => $text,)+
+                    $name::Other(value) => value,
+                }
+            }
+
+            // Parse a fetched value, `None` when it is empty
+            pub fn parse(value: &str) -> Option<Self> {
+                match value.trim() {
+                    "" => None,
+                    $($text => Some($name::$variant),)+
+                    other => Some($name::Other(other.to_string())),
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl ToSql<Text, Pg> for $name {
+            fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+                <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+            }
+        }
+
+        impl FromSql<Text, Pg> for $name {
+            fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+                let value = <String as FromSql<Text, Pg>>::from_sql(bytes)?;
+                Ok($name::parse(&value).unwrap_or($name::Other(value)))
+            }
+        }
+    };
+}
+
+text_enum! {
+    // Kind of release, the `.tick` badge of the site
+    Category {
+        Tv => "TV",
+        Movie => "Movie",
+        Ova => "OVA",
+        Ona => "ONA",
+        Special => "Special",
+        Music => "Music",
+    }
+}
+
+text_enum! {
+    // Age rating, the `.tick-pg` badge of the site
+    AgeRating {
+        G => "G",
+        Pg => "PG",
+        Pg13 => "PG-13",
+        R => "R",
+        RPlus => "R+",
+        Rx => "Rx",
+    }
+}
+
+text_enum! {
+    AiringStatus {
+        FinishedAiring => "Finished Airing",
+        CurrentlyAiring => "Currently Airing",
+        NotYetAired => "Not yet aired",
+    }
+}
+
+text_enum! {
+    // Whether episodes are available subbed, dubbed or both
+    AudioAvailability {
+        Sub => "sub",
+        Dub => "dub",
+        Both => "both",
+    }
+}
+
+text_enum! {
+    // Season of the `premiered` field, e.g. `Fall` in `Fall 2020`
+    Season {
+        Winter => "Winter",
+        Spring => "Spring",
+        Summer => "Summer",
+        Fall => "Fall",
+    }
+}
+
+// Function to parse a duration such as `24m`, `1h 30m` or `2h` into minutes, `None` when
+// it doesn't fit an i32
+pub fn parse_duration_minutes(value: &str) -> Option<i32> {
+    let mut minutes: Option<i32> = None;
+    for part in value.split_whitespace() {
+        let (number, factor) = if let Some(hours) = part.strip_suffix('h') {
+            (hours, 60)
+        } else if let Some(mins) = part.strip_suffix('m') {
+            (mins, 1)
+        } else {
+            return None;
+        };
+        let number: i32 = number.parse().ok()?;
+        minutes = Some(
+            minutes
+                .unwrap_or(0)
+                .checked_add(number.checked_mul(factor)?)?,
+        );
+    }
+    minutes
+}
+
+// Function to parse an aired range such as `Oct 3, 2020 to Mar 27, 2021` into its
+// start and end dates, either of which is `None` when missing (`?`) or not a date
+pub fn parse_aired(value: &str) -> (Option<NaiveDate>, Option<NaiveDate>) {
+    let (start, end) = match value.split_once(" to ") {
+        Some((start, end)) => (start, Some(end)),
+        None => (value, None),
+    };
+    let parse_date = |date: &str| NaiveDate::parse_from_str(date.trim(), AIRED_DATE_FORMAT).ok();
+    (parse_date(start), end.and_then(parse_date))
+}
+
+// Function to parse a premiere such as `Fall 2020` into its season and year
+pub fn parse_premiered(value: &str) -> (Option<Season>, Option<i32>) {
+    match value.trim().rsplit_once(' ') {
+        Some((season, year)) => match year.parse() {
+            Ok(year) => (Season::parse(season), Some(year)),
+            Err(_) => (None, None),
+        },
+        None => (None, None),
+    }
+}
+
+// Function to parse a MAL score, `None` for placeholders such as `n/a` or `?`
+pub fn parse_mal_score(value: &str) -> Option<f32> {
+    value
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|score| score.is_finite())
+}
@@ -0,0 +1,86 @@
+// client.rs
+
+use crate::config::Config;
+use crate::error::Error;
+use crate::model::AnimeID;
+use crate::operations::anime_ops::fetch_data;
+use crate::operations::atoz_ops::get_last_page_no_of_atoz_list;
+use crate::operations::character_ops::{fetch_jikan_characters_response, CharactersResponse};
+use crate::operations::episode_ops::{fetch_anime_details, AnimeDetails};
+use crate::operations::relation_ops::{fetch_jikan_relations_response, RelationsResponse};
+use crate::operations::staff_ops::{fetch_jikan_staff_response, StaffResponse};
+use crate::proxy::{load_proxy_pool, ProxyMode, ProxyPool, ProxyPoolSettings};
+use crate::rate_limit::RateLimiter;
+use std::sync::Arc;
+
+// Client for the remote sources the fetcher reads from
+#[derive(Debug, Clone)]
+pub struct HianimeClient {
+    config: Config,
+    // `None` in direct mode
+    proxies: Option<Arc<ProxyPool>>,
+}
+
+impl HianimeClient {
+    // Create a client without loading the proxy sources. In direct mode it fetches anime
+    // details directly, in pool mode its pool is empty so only the other sources work.
+    // Requests are limited by `RateLimiter::global()`, configured by the first client created
+    pub fn new(config: Config) -> Self {
+        RateLimiter::global().configure_once(&config);
+        let proxies = match config.proxy_mode {
+            ProxyMode::Direct => None,
+            ProxyMode::Pool => Some(Arc::new(ProxyPool::new(
+                vec![],
+                ProxyPoolSettings::from_config(&config),
+            ))),
+        };
+        HianimeClient { config, proxies }
+    }
+
+    // Create a client using the proxies from the configured sources that pass their probe,
+    // failing when fewer pass than PROXY_MIN_HEALTHY. Fetches directly in direct mode
+    pub async fn with_proxies(config: Config) -> Result<Self, Error> {
+        RateLimiter::global().configure_once(&config);
+        let proxies = load_proxy_pool(&config).await?;
+        Ok(HianimeClient { config, proxies })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn proxies(&self) -> Option<&ProxyPool> {
+        self.proxies.as_deref()
+    }
+
+    // Fetch the number of pages in the A-Z list
+    pub async fn last_page_no(&self) -> Result<u16, Error> {
+        get_last_page_no_of_atoz_list(&self.config).await
+    }
+
+    // Fetch the anime IDs listed on a page of the A-Z list
+    pub async fn anime_ids(&self, page_no: u16) -> Result<Vec<AnimeID>, Error> {
+        fetch_data(&self.config, page_no).await
+    }
+
+    // Fetch the details and episodes of an anime through a proxy chosen by score, or
+    // directly in direct mode
+    pub async fn anime_details(&self, anime_id: &str) -> Result<AnimeDetails, Error> {
+        fetch_anime_details(&self.config, anime_id.to_string(), self.proxies()).await
+    }
+
+    // Fetch the characters and voice actors of an anime from Jikan
+    pub async fn characters(&self, mal_id: i32) -> Result<CharactersResponse, Error> {
+        fetch_jikan_characters_response(&self.config, mal_id).await
+    }
+
+    // Fetch the relations of an anime to other anime and manga from Jikan
+    pub async fn relations(&self, mal_id: i32) -> Result<RelationsResponse, Error> {
+        fetch_jikan_relations_response(&self.config, mal_id).await
+    }
+
+    // Fetch the staff of an anime from Jikan
+    pub async fn staff(&self, mal_id: i32) -> Result<StaffResponse, Error> {
+        fetch_jikan_staff_response(&self.config, mal_id).await
+    }
+}
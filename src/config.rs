@@ -0,0 +1,505 @@
+// config.rs
+
+use crate::error::Error;
+use crate::operations::atoz_ops::ATOZ_LIST_PAGE_URL;
+use crate::proxy::{ProxyMode, ProxyScheme, ProxySource};
+use crate::rate_limit::{host_key, parse_rates, Rate};
+use dotenvy::dotenv;
+use reqwest::Url;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+// Environment variable pointing at the optional TOML config file
+pub const CONFIG_FILE_VAR: &str = "HIANIME_CONFIG";
+
+// Every setting, named as in the environment; the TOML file uses the lowercase names
+const SETTINGS: [&str; 27] = [
+    "DATABASE_URL",
+    "DATABASE_POOL_SIZE",
+    "DATABASE_POOL_TIMEOUT_SECS",
+    "ATOZLIST_URL",
+    "ANIME_FETCHER_URL",
+    "JIKAN_API_URL",
+    "ANILIST_API_URL",
+    "SOCK5_URL",
+    "SOCK4_URL",
+    "HTTP_URL",
+    "PROXY_SOURCES",
+    "PROXY_MODE",
+    "PROXY_PROBE_URL",
+    "PROXY_PROBE_TIMEOUT_SECS",
+    "PROXY_PROBE_CONCURRENCY",
+    "PROXY_MAX_FAILURES",
+    "PROXY_MIN_HEALTHY",
+    "PROXY_REVALIDATE_SECS",
+    "SYNC_CONCURRENCY",
+    "JIKAN_RATE_LIMIT",
+    "ATOZ_RATE_LIMIT",
+    "ANIME_FETCHER_RATE_LIMIT",
+    "ANILIST_RATE_LIMIT",
+    "HIANIME_RATE_LIMIT",
+    "RETRY_MAX_ATTEMPTS",
+    "RETRY_BASE_DELAY_MS",
+    "RETRY_MAX_DELAY_SECS",
+];
+
+const DEFAULT_POOL_SIZE: u32 = 10;
+const DEFAULT_POOL_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_SYNC_CONCURRENCY: usize = 10;
+const DEFAULT_PROXY_PROBE_TIMEOUT_SECS: u64 = 5;
+const DEFAULT_PROXY_PROBE_CONCURRENCY: usize = 50;
+const DEFAULT_PROXY_MAX_FAILURES: u32 = 3;
+const DEFAULT_PROXY_MIN_HEALTHY: usize = 1;
+const DEFAULT_PROXY_REVALIDATE_SECS: u64 = 300;
+const DEFAULT_JIKAN_API_URL: &str = "https://api.jikan.moe/v4";
+// Jikan v4 answers 429 past 3 requests per second or 60 per minute
+const DEFAULT_JIKAN_RATE_LIMIT: &str = "3/s,60/min";
+const DEFAULT_ANILIST_API_URL: &str = "https://graphql.anilist.co";
+// AniList allows 90 requests per minute, lowered to 30 while its API is degraded
+const DEFAULT_ANILIST_RATE_LIMIT: &str = "30/min";
+const DEFAULT_ATOZ_RATE_LIMIT: &str = "5/s";
+const DEFAULT_ANIME_FETCHER_RATE_LIMIT: &str = "10/s";
+// The A-Z list page of the site itself is only read for its page count
+const DEFAULT_HIANIME_RATE_LIMIT: &str = "1/s";
+const DEFAULT_RETRY_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_RETRY_BASE_DELAY_MS: u64 = 500;
+const DEFAULT_RETRY_MAX_DELAY_SECS: u64 = 30;
+
+// Settings of the connection pool, all the commands that only read the database need
+#[derive(Debug, Clone)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub pool_size: u32,
+    pub pool_timeout: Duration,
+}
+
+impl DatabaseConfig {
+    // Load the database settings like `Config::load`, without requiring or checking the
+    // settings of the fetches
+    pub fn load(path: Option<&Path>) -> Result<DatabaseConfig, Error> {
+        let mut loader = Loader::read(path);
+        let database = loader.database();
+        loader.finish(database)
+    }
+}
+
+// Settings shared by every operation, loaded and validated once at startup
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub database: DatabaseConfig,
+    pub atoz_list_url: String,
+    pub anime_fetcher_url: String,
+    pub jikan_api_url: String,
+    pub anilist_api_url: String,
+    // Where proxies are loaded from, including the lists of SOCK5_URL, SOCK4_URL and HTTP_URL
+    pub proxy_sources: Vec<ProxySource>,
+    pub proxy_mode: ProxyMode,
+    // URL fetched through every proxy to check it works, the anime fetcher by default
+    pub proxy_probe_url: String,
+    pub proxy_probe_timeout: Duration,
+    pub proxy_probe_concurrency: usize,
+    // Failures in a row after which a proxy is evicted from the pool
+    pub proxy_max_failures: u32,
+    // Healthy proxies below which a sync is aborted
+    pub proxy_min_healthy: usize,
+    pub proxy_revalidate_interval: Duration,
+    // Jobs a sync runs at once, shared by every pipeline
+    pub sync_concurrency: usize,
+    // Requests allowed per host, every rate of a list must hold at once
+    pub jikan_rate_limit: Vec<Rate>,
+    pub atoz_rate_limit: Vec<Rate>,
+    pub anime_fetcher_rate_limit: Vec<Rate>,
+    pub anilist_rate_limit: Vec<Rate>,
+    pub hianime_rate_limit: Vec<Rate>,
+    // Attempts of a fetch failing with transient errors, and the waits in between
+    pub retry_max_attempts: u32,
+    pub retry_base_delay: Duration,
+    pub retry_max_delay: Duration,
+}
+
+impl Config {
+    // Every configured rate limit with its setting and the URL whose host it applies to
+    pub fn rate_limits(&self) -> [(&'static str, &str, &[Rate]); 5] {
+        [
+            (
+                "JIKAN_RATE_LIMIT",
+                &self.jikan_api_url,
+                &self.jikan_rate_limit,
+            ),
+            (
+                "ANILIST_RATE_LIMIT",
+                &self.anilist_api_url,
+                &self.anilist_rate_limit,
+            ),
+            (
+                "ATOZ_RATE_LIMIT",
+                &self.atoz_list_url,
+                &self.atoz_rate_limit,
+            ),
+            (
+                "ANIME_FETCHER_RATE_LIMIT",
+                &self.anime_fetcher_url,
+                &self.anime_fetcher_rate_limit,
+            ),
+            (
+                "HIANIME_RATE_LIMIT",
+                ATOZ_LIST_PAGE_URL,
+                &self.hianime_rate_limit,
+            ),
+        ]
+    }
+
+    // Like `rate_limits`, with the limits open to changes
+    fn rate_limits_mut(&mut self) -> [(&'static str, &str, &mut Vec<Rate>); 5] {
+        [
+            (
+                "JIKAN_RATE_LIMIT",
+                &self.jikan_api_url,
+                &mut self.jikan_rate_limit,
+            ),
+            (
+                "ANILIST_RATE_LIMIT",
+                &self.anilist_api_url,
+                &mut self.anilist_rate_limit,
+            ),
+            (
+                "ATOZ_RATE_LIMIT",
+                &self.atoz_list_url,
+                &mut self.atoz_rate_limit,
+            ),
+            (
+                "ANIME_FETCHER_RATE_LIMIT",
+                &self.anime_fetcher_url,
+                &mut self.anime_fetcher_rate_limit,
+            ),
+            (
+                "HIANIME_RATE_LIMIT",
+                ATOZ_LIST_PAGE_URL,
+                &mut self.hianime_rate_limit,
+            ),
+        ]
+    }
+
+    // Load from the environment, `.env` and the TOML file at `path` or $HIANIME_CONFIG,
+    // with the environment taking precedence over the file
+    pub fn load(path: Option<&Path>) -> Result<Config, Error> {
+        Loader::read(path).build()
+    }
+}
+
+// Rate limits of one host while merging them, the first setting that was set and every
+// default of the settings that were not
+struct HostLimits {
+    host: String,
+    set: Option<(&'static str, Vec<Rate>)>,
+    defaults: Vec<Rate>,
+}
+
+// Collects raw values and every problem found while validating them
+#[derive(Default)]
+struct Loader {
+    // Later entries win, so file values come before environment values
+    values: Vec<(String, String)>,
+    problems: Vec<String>,
+}
+
+impl Loader {
+    // Collect the values of the environment, `.env` and the TOML file at `path` or
+    // $HIANIME_CONFIG
+    fn read(path: Option<&Path>) -> Loader {
+        dotenv().ok();
+
+        let path = path
+            .map(Path::to_path_buf)
+            .or_else(|| env::var_os(CONFIG_FILE_VAR).map(PathBuf::from));
+
+        let mut loader = Loader::default();
+        if let Some(path) = path {
+            loader.read_file(&path);
+        }
+        for name in SETTINGS {
+            if let Ok(value) = env::var(name) {
+                loader.values.push((name.to_string(), value));
+            }
+        }
+        loader
+    }
+
+    fn read_file(&mut self, path: &Path) {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                self.problems
+                    .push(format!("cannot read {}: {}", path.display(), err));
+                return;
+            }
+        };
+        let table = match contents.parse::<toml::Table>() {
+            Ok(table) => table,
+            Err(err) => {
+                self.problems
+                    .push(format!("cannot parse {}: {}", path.display(), err));
+                return;
+            }
+        };
+
+        for (key, value) in table {
+            let name = key.to_uppercase();
+            if !SETTINGS.contains(&name.as_str()) {
+                self.problems
+                    .push(format!("unknown key `{}` in {}", key, path.display()));
+                continue;
+            }
+            match value {
+                toml::Value::String(value) => self.values.push((name, value)),
+                toml::Value::Integer(value) => self.values.push((name, value.to_string())),
+                _ => self.problems.push(format!(
+                    "`{}` in {} must be a string or an integer",
+                    key,
+                    path.display()
+                )),
+            }
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .rev()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.trim())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn required(&mut self, name: &str) -> String {
+        match self.get(name) {
+            Some(value) => value.to_string(),
+            None => {
+                self.problems.push(format!("{} must be set", name));
+                String::new()
+            }
+        }
+    }
+
+    fn url(&mut self, name: &str, default: Option<&str>) -> String {
+        let value = match (self.get(name), default) {
+            (Some(value), _) => value.to_string(),
+            (None, Some(default)) => return default.to_string(),
+            (None, None) => return self.required(name),
+        };
+        if let Err(err) = Url::parse(&value) {
+            self.problems.push(format!(
+                "{} is not a valid URL (`{}`): {}",
+                name, value, err
+            ));
+        }
+        value
+    }
+
+    fn number<T>(&mut self, name: &str, default: T) -> T
+    where
+        T: FromStr + PartialOrd + Default,
+        T::Err: Display,
+    {
+        let Some(value) = self.get(name) else {
+            return default;
+        };
+        match value.parse::<T>() {
+            Ok(number) if number > T::default() => number,
+            Ok(_) => {
+                self.problems
+                    .push(format!("{} must be greater than zero", name));
+                default
+            }
+            Err(err) => {
+                self.problems
+                    .push(format!("{} must be a number (`{}`): {}", name, value, err));
+                default
+            }
+        }
+    }
+
+    fn rates(&mut self, name: &str, default: &str) -> Vec<Rate> {
+        let value = self.get(name).unwrap_or(default).to_string();
+        match parse_rates(&value) {
+            Ok(rates) => rates,
+            Err(err) => {
+                self.problems
+                    .push(format!("{} is not a valid rate limit: {}", name, err));
+                parse_rates(default).unwrap_or_default()
+            }
+        }
+    }
+
+    fn optional_url(&mut self, name: &str) -> Option<String> {
+        self.get(name)?;
+        Some(self.url(name, None))
+    }
+
+    // Sources listed in PROXY_SOURCES, separated by commas or new lines, followed by the
+    // lists of SOCK5_URL, SOCK4_URL and HTTP_URL
+    fn proxy_sources(&mut self) -> Vec<ProxySource> {
+        let listed = self.get("PROXY_SOURCES").unwrap_or_default().to_string();
+        let mut sources = Vec::new();
+        for entry in listed.split([',', '\n']).map(str::trim) {
+            if entry.is_empty() {
+                continue;
+            }
+            match entry.parse() {
+                Ok(source) => sources.push(source),
+                Err(err) => self
+                    .problems
+                    .push(format!("PROXY_SOURCES has an invalid source: {}", err)),
+            }
+        }
+
+        for (name, scheme) in [
+            ("SOCK5_URL", ProxyScheme::Socks5),
+            ("SOCK4_URL", ProxyScheme::Socks4),
+            ("HTTP_URL", ProxyScheme::Http),
+        ] {
+            if let Some(url) = self.optional_url(name) {
+                sources.push(ProxySource::Url { scheme, url });
+            }
+        }
+        sources
+    }
+
+    fn proxy_mode(&mut self) -> ProxyMode {
+        let Some(value) = self.get("PROXY_MODE") else {
+            return ProxyMode::default();
+        };
+        match value.parse() {
+            Ok(mode) => mode,
+            Err(err) => {
+                self.problems.push(format!("PROXY_MODE {}", err));
+                ProxyMode::default()
+            }
+        }
+    }
+
+    // Limits are kept per host, so the limits of URLs on one host are merged. Limits that
+    // were set must agree and apply to every URL of the host; a host with default limits
+    // only gets all of them, every rate of which must hold, so the strictest one wins
+    fn merge_rate_limits(&mut self, config: &mut Config) {
+        let mut hosts: Vec<HostLimits> = Vec::new();
+        for (name, url, rates) in config.rate_limits() {
+            let Some(host) = host_key(url) else {
+                continue;
+            };
+            let index = match hosts.iter().position(|limits| limits.host == host) {
+                Some(index) => index,
+                None => {
+                    hosts.push(HostLimits {
+                        host,
+                        set: None,
+                        defaults: Vec::new(),
+                    });
+                    hosts.len() - 1
+                }
+            };
+            let limits = &mut hosts[index];
+
+            if self.get(name).is_none() {
+                for rate in rates {
+                    if !limits.defaults.contains(rate) {
+                        limits.defaults.push(*rate);
+                    }
+                }
+                continue;
+            }
+            match &limits.set {
+                Some((other_name, other_rates)) if other_rates.as_slice() != rates => {
+                    self.problems.push(format!(
+                        "{} and {} limit the same host {} differently",
+                        other_name, name, limits.host
+                    ));
+                }
+                Some(_) => {}
+                None => limits.set = Some((name, rates.to_vec())),
+            }
+        }
+
+        for (_, url, rates) in config.rate_limits_mut() {
+            let Some(host) = host_key(url) else {
+                continue;
+            };
+            if let Some(limits) = hosts.iter().find(|limits| limits.host == host) {
+                *rates = match &limits.set {
+                    Some((_, set)) => set.clone(),
+                    None => limits.defaults.clone(),
+                };
+            }
+        }
+    }
+
+    fn database(&mut self) -> DatabaseConfig {
+        DatabaseConfig {
+            url: self.required("DATABASE_URL"),
+            pool_size: self.number("DATABASE_POOL_SIZE", DEFAULT_POOL_SIZE),
+            pool_timeout: Duration::from_secs(
+                self.number("DATABASE_POOL_TIMEOUT_SECS", DEFAULT_POOL_TIMEOUT_SECS),
+            ),
+        }
+    }
+
+    fn build(mut self) -> Result<Config, Error> {
+        let anime_fetcher_url = self.url("ANIME_FETCHER_URL", None);
+        let mut config = Config {
+            database: self.database(),
+            atoz_list_url: self.url("ATOZLIST_URL", None),
+            proxy_probe_url: self.url("PROXY_PROBE_URL", Some(&anime_fetcher_url)),
+            anime_fetcher_url,
+            jikan_api_url: self.url("JIKAN_API_URL", Some(DEFAULT_JIKAN_API_URL)),
+            anilist_api_url: self.url("ANILIST_API_URL", Some(DEFAULT_ANILIST_API_URL)),
+            proxy_sources: self.proxy_sources(),
+            proxy_mode: self.proxy_mode(),
+            proxy_probe_timeout: Duration::from_secs(
+                self.number("PROXY_PROBE_TIMEOUT_SECS", DEFAULT_PROXY_PROBE_TIMEOUT_SECS),
+            ),
+            proxy_probe_concurrency: self
+                .number("PROXY_PROBE_CONCURRENCY", DEFAULT_PROXY_PROBE_CONCURRENCY),
+            proxy_max_failures: self.number("PROXY_MAX_FAILURES", DEFAULT_PROXY_MAX_FAILURES),
+            proxy_min_healthy: self.number("PROXY_MIN_HEALTHY", DEFAULT_PROXY_MIN_HEALTHY),
+            proxy_revalidate_interval: Duration::from_secs(
+                self.number("PROXY_REVALIDATE_SECS", DEFAULT_PROXY_REVALIDATE_SECS),
+            ),
+            sync_concurrency: self.number("SYNC_CONCURRENCY", DEFAULT_SYNC_CONCURRENCY),
+            jikan_rate_limit: self.rates("JIKAN_RATE_LIMIT", DEFAULT_JIKAN_RATE_LIMIT),
+            atoz_rate_limit: self.rates("ATOZ_RATE_LIMIT", DEFAULT_ATOZ_RATE_LIMIT),
+            anime_fetcher_rate_limit: self
+                .rates("ANIME_FETCHER_RATE_LIMIT", DEFAULT_ANIME_FETCHER_RATE_LIMIT),
+            anilist_rate_limit: self.rates("ANILIST_RATE_LIMIT", DEFAULT_ANILIST_RATE_LIMIT),
+            hianime_rate_limit: self.rates("HIANIME_RATE_LIMIT", DEFAULT_HIANIME_RATE_LIMIT),
+            retry_max_attempts: self.number("RETRY_MAX_ATTEMPTS", DEFAULT_RETRY_MAX_ATTEMPTS),
+            retry_base_delay: Duration::from_millis(
+                self.number("RETRY_BASE_DELAY_MS", DEFAULT_RETRY_BASE_DELAY_MS),
+            ),
+            retry_max_delay: Duration::from_secs(
+                self.number("RETRY_MAX_DELAY_SECS", DEFAULT_RETRY_MAX_DELAY_SECS),
+            ),
+        };
+
+        if config.proxy_mode == ProxyMode::Pool && config.proxy_sources.is_empty() {
+            self.problems.push(String::from(
+                "PROXY_SOURCES, SOCK5_URL, SOCK4_URL or HTTP_URL must be set unless PROXY_MODE is direct",
+            ));
+        }
+
+        self.merge_rate_limits(&mut config);
+        self.finish(config)
+    }
+
+    // The loaded settings, unless a problem was found
+    fn finish<T>(self, settings: T) -> Result<T, Error> {
+        if self.problems.is_empty() {
+            Ok(settings)
+        } else {
+            Err(Error::Config(self.problems))
+        }
+    }
+}
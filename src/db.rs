@@ -1,12 +1,24 @@
+use crate::config::DatabaseConfig;
 use diesel::pg::PgConnection;
-use diesel::prelude::*;
-use dotenvy::dotenv;
-use std::env;
+use diesel::r2d2::{ConnectionManager, Pool, PoolError};
+use std::time::Duration;
 
-pub fn establish_connection() -> PgConnection {
-    dotenv().ok();
+pub type PgPool = Pool<ConnectionManager<PgConnection>>;
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    PgConnection::establish(&database_url)
-        .unwrap_or_else(|_| panic!("Error connecting to {database_url}"))
+// Create the shared connection pool from the config
+pub fn establish_pool(config: &DatabaseConfig) -> Result<PgPool, PoolError> {
+    build_pool(&config.url, config.pool_size, config.pool_timeout)
+}
+
+// Create a connection pool with the given size and checkout timeout
+pub fn build_pool(
+    database_url: &str,
+    max_size: u32,
+    connection_timeout: Duration,
+) -> Result<PgPool, PoolError> {
+    let manager = ConnectionManager::<PgConnection>::new(database_url);
+    Pool::builder()
+        .max_size(max_size)
+        .connection_timeout(connection_timeout)
+        .build(manager)
 }
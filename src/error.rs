@@ -0,0 +1,222 @@
+// error.rs
+
+use crate::operations::record_ops::WriteError;
+use diesel::r2d2::PoolError;
+use diesel::result::Error as DieselError;
+use reqwest::StatusCode;
+use std::error::Error as StdError;
+use std::fmt;
+use std::fmt::Formatter;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::task::JoinError;
+
+// Error type shared by every operation in the crate
+#[derive(Debug)]
+pub enum Error {
+    // The request could not be sent or its body could not be read
+    Request {
+        url: String,
+        source: reqwest::Error,
+    },
+    // An HTTP client could not be built, with the proxy it was meant for
+    Client {
+        proxy: Option<String>,
+        source: reqwest::Error,
+    },
+    // The server answered with an unexpected status
+    Http {
+        url: String,
+        status: StatusCode,
+    },
+    // The server answered 404
+    NotFound {
+        url: String,
+    },
+    // The server answered 429, with the Retry-After delay when it sent one
+    RateLimited {
+        url: String,
+        retry_after: Option<Duration>,
+    },
+    // The response body did not have the expected shape
+    Parse {
+        url: String,
+        message: String,
+    },
+    // A selector failed to parse or matched nothing, usually a page layout change
+    Selector {
+        url: String,
+        selector: String,
+        message: String,
+    },
+    // A local file could not be read
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    // An error fetching or storing an anime, with the name or ID of the anime
+    Anime {
+        anime: String,
+        source: Box<Error>,
+    },
+    Db(DieselError),
+    Pool(PoolError),
+    Write(WriteError),
+    // Missing or malformed configuration, one entry per problem
+    Config(Vec<String>),
+    Join(JoinError),
+    NoProxiesAvailable,
+    // Fewer proxies passed their probes or are left after evictions than a sync needs
+    TooFewProxies {
+        healthy: usize,
+        required: usize,
+    },
+}
+
+impl Error {
+    // Tie the error to the anime it happened for, unless it already is
+    pub fn for_anime(self, anime: impl Into<String>) -> Error {
+        match self {
+            Error::Anime { .. } => self,
+            source => Error::Anime {
+                anime: anime.into(),
+                source: Box::new(source),
+            },
+        }
+    }
+
+    // Name or ID of the anime the error happened for
+    pub fn anime(&self) -> Option<&str> {
+        match self {
+            Error::Anime { anime, .. } => Some(anime),
+            _ => None,
+        }
+    }
+
+    // URL of the request that failed, for errors raised while fetching
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            Error::Anime { source, .. } => source.url(),
+            Error::Request { url, .. }
+            | Error::Http { url, .. }
+            | Error::NotFound { url }
+            | Error::RateLimited { url, .. }
+            | Error::Parse { url, .. }
+            | Error::Selector { url, .. } => Some(url),
+            _ => None,
+        }
+    }
+
+    // Whether sending the same request again may succeed: connections that failed or timed
+    // out, 429s and server errors. A 404, other client errors and bodies of the wrong shape
+    // come back the same every time
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Anime { source, .. } => source.is_transient(),
+            Error::Request { .. } | Error::RateLimited { .. } => true,
+            Error::Http { status, .. } => {
+                status.is_server_error() || *status == StatusCode::REQUEST_TIMEOUT
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Request { url, source } => write!(f, "Request to {} failed: {}", url, source),
+            Error::Client {
+                proxy: Some(proxy),
+                source,
+            } => write!(f, "Cannot build a client for proxy {}: {}", proxy, source),
+            Error::Client {
+                proxy: None,
+                source,
+            } => write!(f, "Cannot build a client: {}", source),
+            Error::Http { url, status } => write!(f, "{} answered with {}", url, status),
+            Error::NotFound { url } => write!(f, "{} was not found", url),
+            Error::RateLimited {
+                url,
+                retry_after: Some(delay),
+            } => write!(
+                f,
+                "{} is rate limited, retry after {}s",
+                url,
+                delay.as_secs()
+            ),
+            Error::RateLimited {
+                url,
+                retry_after: None,
+            } => write!(f, "{} is rate limited", url),
+            Error::Parse { url, message } => {
+                write!(f, "Unexpected response from {}: {}", url, message)
+            }
+            Error::Selector {
+                url,
+                selector,
+                message,
+            } => write!(f, "Selector `{}` on {}: {}", selector, url, message),
+            Error::Io { path, source } => write!(f, "Cannot read {}: {}", path.display(), source),
+            Error::Anime { anime, source } => write!(f, "anime {}: {}", anime, source),
+            Error::Db(err) => write!(f, "Diesel Error: {}", err),
+            Error::Pool(err) => write!(f, "Pool Error: {}", err),
+            Error::Write(err) => write!(f, "{}", err),
+            Error::Config(problems) => {
+                write!(f, "Invalid configuration:")?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+            Error::Join(err) => write!(f, "Join Error: {}", err),
+            Error::NoProxiesAvailable => write!(f, "No proxies available"),
+            Error::TooFewProxies { healthy, required } => write!(
+                f,
+                "Only {} healthy proxies left, at least {} are required (PROXY_MIN_HEALTHY)",
+                healthy, required
+            ),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Request { source, .. } => Some(source),
+            Error::Client { source, .. } => Some(source),
+            Error::Anime { source, .. } => Some(source.as_ref()),
+            Error::Io { source, .. } => Some(source),
+            Error::Db(err) => Some(err),
+            Error::Pool(err) => Some(err),
+            Error::Write(err) => Some(err),
+            Error::Join(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<DieselError> for Error {
+    fn from(err: DieselError) -> Self {
+        Error::Db(err)
+    }
+}
+
+impl From<PoolError> for Error {
+    fn from(err: PoolError) -> Self {
+        Error::Pool(err)
+    }
+}
+
+impl From<WriteError> for Error {
+    fn from(err: WriteError) -> Self {
+        Error::Write(err)
+    }
+}
+
+impl From<JoinError> for Error {
+    fn from(err: JoinError) -> Self {
+        Error::Join(err)
+    }
+}
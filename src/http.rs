@@ -0,0 +1,230 @@
+// http.rs
+
+use crate::error::Error;
+use crate::proxy::Proxy;
+use crate::rate_limit::RateLimiter;
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, RETRY_AFTER};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+// Headers of a desktop Firefox, the same ones the scraper in `api/` sends
+const USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:122.0) Gecko/20100101 Firefox/122.0";
+const ACCEPT_HTML: &str =
+    "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,*/*;q=0.8";
+// Accept header of the JSON APIs, Jikan, AniList and the anime list
+const ACCEPT_JSON: &str = "application/json";
+// Limits of every client, single requests such as proxy probes can set a shorter timeout
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+// Idle connections are kept this long for the next request to the same host
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+// What a client asks for, pages of the site like a browser or JSON from an API
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Accept {
+    Html,
+    Json,
+}
+
+impl Accept {
+    fn header(self) -> &'static str {
+        match self {
+            Accept::Html => ACCEPT_HTML,
+            Accept::Json => ACCEPT_JSON,
+        }
+    }
+}
+
+// Clients with the shared defaults, one per proxy plus the ones sending requests directly to
+// the site and to the APIs. A client keeps its connections open, so building one per
+// request would open a new connection and TLS session every time
+#[derive(Debug, Default)]
+pub struct ClientFactory {
+    // Keyed by what the client accepts and its proxy, `None` for direct clients
+    clients: Mutex<HashMap<(Accept, Option<Proxy>), Client>>,
+}
+
+impl ClientFactory {
+    pub fn new() -> Self {
+        ClientFactory::default()
+    }
+
+    // Factory shared by every request of the process
+    pub fn global() -> &'static ClientFactory {
+        static FACTORY: OnceLock<ClientFactory> = OnceLock::new();
+        FACTORY.get_or_init(ClientFactory::new)
+    }
+
+    // Client scraping the site without a proxy
+    pub fn direct(&self) -> Result<Client, Error> {
+        self.client(Accept::Html, None)
+    }
+
+    // Client sending requests to the JSON APIs
+    pub fn api(&self) -> Result<Client, Error> {
+        self.client(Accept::Json, None)
+    }
+
+    // Client scraping the site through `proxy`
+    pub fn proxied(&self, proxy: &Proxy) -> Result<Client, Error> {
+        self.client(Accept::Html, Some(proxy))
+    }
+
+    // Drop the client of a proxy that left the pool, closing its idle connections
+    pub fn forget(&self, proxy: &Proxy) {
+        self.clients
+            .lock()
+            .unwrap()
+            .remove(&(Accept::Html, Some(proxy.clone())));
+    }
+
+    // Clients built so far
+    pub fn len(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn client(&self, accept: Accept, proxy: Option<&Proxy>) -> Result<Client, Error> {
+        let key = (accept, proxy.cloned());
+        let mut clients = self.clients.lock().unwrap();
+        if let Some(client) = clients.get(&key) {
+            return Ok(client.clone());
+        }
+        let client = build_client(accept, proxy).map_err(|source| Error::Client {
+            proxy: proxy.map(Proxy::to_string),
+            source,
+        })?;
+        clients.insert(key, client.clone());
+        Ok(client)
+    }
+}
+
+// Build a client with the shared defaults. Responses are decompressed by reqwest, which
+// also sends the matching Accept-Encoding
+fn build_client(accept: Accept, proxy: Option<&Proxy>) -> Result<Client, reqwest::Error> {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, HeaderValue::from_static(accept.header()));
+
+    let mut builder = Client::builder()
+        .user_agent(USER_AGENT)
+        .default_headers(headers)
+        .gzip(true)
+        .brotli(true)
+        .deflate(true)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy.url())?);
+    }
+    builder.build()
+}
+
+// Send a GET request once the host's rate limit allows it and turn error statuses into
+// the matching Error
+pub async fn get(client: &Client, url: &str) -> Result<Response, Error> {
+    send(url, client.get(url)).await
+}
+
+// Send a GET request like `get`, giving up on it after `timeout` instead of the default
+pub async fn get_with_timeout(
+    client: &Client,
+    url: &str,
+    timeout: Duration,
+) -> Result<Response, Error> {
+    send(url, client.get(url).timeout(timeout)).await
+}
+
+// Send a POST request with a JSON body, rate limited and checked like `get`
+pub async fn post_json<T: Serialize + ?Sized>(
+    client: &Client,
+    url: &str,
+    body: &T,
+) -> Result<Response, Error> {
+    send(url, client.post(url).json(body)).await
+}
+
+// Send the request once the host's rate limit allows it. A 429 holds back every request to
+// the host for its Retry-After delay, sending it again is left to `RetryPolicy`
+async fn send(url: &str, request: RequestBuilder) -> Result<Response, Error> {
+    let limiter = RateLimiter::global();
+    limiter.acquire(url).await;
+    let response = request.send().await.map_err(|source| Error::Request {
+        url: url.to_string(),
+        source,
+    })?;
+
+    let result = check_status(url, response);
+    if let Err(Error::RateLimited {
+        retry_after: Some(delay),
+        ..
+    }) = &result
+    {
+        limiter.pause(url, *delay);
+    }
+    result
+}
+
+// Keep successful responses, map 404, 429 and other statuses to errors
+pub fn check_status(url: &str, response: Response) -> Result<Response, Error> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let url = url.to_string();
+    Err(match status {
+        StatusCode::NOT_FOUND => Error::NotFound { url },
+        StatusCode::TOO_MANY_REQUESTS => Error::RateLimited {
+            url,
+            retry_after: retry_after(&response),
+        },
+        _ => Error::Http { url, status },
+    })
+}
+
+// Delay requested by a Retry-After header, given either in seconds or as an HTTP date
+pub fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    parse_retry_after(value, Utc::now())
+}
+
+// Delay of a Retry-After value at `now`
+pub fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    // HTTP dates are RFC 2822 dates in GMT, a date in the past means retry now
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+pub async fn read_text(url: &str, response: Response) -> Result<String, Error> {
+    response.text().await.map_err(|source| Error::Request {
+        url: url.to_string(),
+        source,
+    })
+}
+
+// Read the body and deserialize it, keeping serde's message on failure
+pub async fn read_json<T: DeserializeOwned>(url: &str, response: Response) -> Result<T, Error> {
+    let body = read_text(url, response).await?;
+    serde_json::from_str(&body).map_err(|err| Error::Parse {
+        url: url.to_string(),
+        message: err.to_string(),
+    })
+}
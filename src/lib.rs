@@ -1,14 +1,33 @@
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
+pub mod anime_fields;
+pub mod client;
+pub mod config;
+pub mod db;
+pub mod error;
+pub mod http;
+pub mod model;
+pub mod proxy;
+pub mod rate_limit;
+pub mod retry;
+pub mod scheduler;
+pub mod schema;
+pub mod store;
+pub mod operations {
+    pub mod anilist_ops;
+    pub mod anime_ops;
+    pub mod atoz_ops;
+    pub mod character_ops;
+    pub mod episode_ops;
+    pub mod history_ops;
+    pub mod jikan_ops;
+    pub mod record_ops;
+    pub mod relation_ops;
+    pub mod staff_ops;
+    pub mod sync_run_ops;
+    pub mod sync_state_ops;
+    pub mod taxonomy_ops;
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub use client::HianimeClient;
+pub use config::{Config, DatabaseConfig};
+pub use error::Error;
+pub use store::Store;
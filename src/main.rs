@@ -1,69 +1,505 @@
-mod db;
-mod model;
-mod schema;
-mod operations {
-    pub mod anime_ops;
-    pub mod atoz_ops;
-    pub mod episode_ops;
-    pub mod staff_ops;
-}
+use std::env;
+use std::future::Future;
+use std::path::PathBuf;
+use std::process::ExitCode;
 
-extern crate reqwest;
-extern crate serde;
-extern crate serde_json;
+use chrono::TimeDelta;
+use clap::{Parser, Subcommand};
+use hianime_data_fetcher::operations::anilist_ops::store_enrichment_data;
+use hianime_data_fetcher::operations::anime_ops::add_new_anime_with_anime_id;
+use hianime_data_fetcher::operations::character_ops::store_character_data;
+use hianime_data_fetcher::operations::episode_ops::store_anime_and_episode_data;
+use hianime_data_fetcher::operations::relation_ops::store_relation_data;
+use hianime_data_fetcher::operations::staff_ops::store_staff_data;
+use hianime_data_fetcher::operations::sync_run_ops::{RunCounts, RunRecorder};
+use hianime_data_fetcher::operations::sync_state_ops::{
+    SyncMode, CHARACTERS_PIPELINE, RELATIONS_PIPELINE, STAFF_PIPELINE,
+};
+use hianime_data_fetcher::operations::taxonomy_ops::AnimeFilter;
+use hianime_data_fetcher::rate_limit::RateLimiter;
+use hianime_data_fetcher::scheduler::Scheduler;
+use hianime_data_fetcher::{Config, DatabaseConfig, Error, HianimeClient, Store};
 
-use std::vec;
+// Exit code used when the requested record does not exist
+const EXIT_NOT_FOUND: u8 = 3;
+// Exit code used when the configuration is missing or invalid, as in sysexits.h
+const EXIT_CONFIG: u8 = 78;
 
-use model::Episode;
-use operations::atoz_ops::get_last_page_no_of_atoz_list;
-use operations::episode_ops::{
-    add_new_episode, fetch_anime_details, load_proxies, store_anime_and_episode_data,
-};
-use operations::staff_ops::{
-    fetch_jikan_staff_response, insert_into_anime_staff, insert_or_update_staff, StaffResponse,
-};
-use reqwest::Error;
-use serde::{Deserialize, Serialize};
+/// Fetch anime, episode and staff data and store it in Postgres
+#[derive(Debug, Parser)]
+#[command(name = "hianime-data-fetcher", version, about)]
+struct Cli {
+    /// TOML config file, overridden by the environment [default: $HIANIME_CONFIG]
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
 
-use crate::anime_ops::{
-    add_new_anime, add_new_anime_with_anime_id, load_all_anime, load_all_anime_ids,
-};
-use crate::db::establish_connection;
-use crate::model::Anime;
-use crate::operations::anime_ops;
-use diesel::result::Error as DieselError;
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Scrape every page of the A-Z list and store the anime IDs
+    SyncIds,
+    /// Fetch details and episodes for every stored anime ID, resuming an interrupted run
+    SyncDetails {
+        /// Only fetch the anime whose last attempt failed
+        #[arg(long, conflicts_with = "restart")]
+        retry_failed: bool,
+        /// Fetch every anime again instead of resuming
+        #[arg(long)]
+        restart: bool,
+        /// Also fetch again the anime whose details are older than this many days
+        #[arg(long, value_name = "DAYS", conflicts_with_all = ["retry_failed", "restart"])]
+        stale_days: Option<u32>,
+    },
+    /// Fetch staff from Jikan for every anime with a MAL ID, or for a single MAL ID
+    SyncStaff {
+        /// Only fetch the staff of the anime with this MAL ID
+        #[arg(long, conflicts_with = "incremental")]
+        mal_id: Option<i32>,
+        /// Skip MAL IDs whose staff were synced within --max-age-days
+        #[arg(long)]
+        incremental: bool,
+        /// How long synced staff stay fresh in incremental mode
+        #[arg(
+            long,
+            value_name = "DAYS",
+            default_value_t = 7,
+            requires = "incremental"
+        )]
+        max_age_days: u32,
+    },
+    /// Fetch characters and voice actors from Jikan for every anime with a MAL ID, or for a
+    /// single MAL ID
+    SyncCharacters {
+        /// Only fetch the characters of the anime with this MAL ID
+        #[arg(long, conflicts_with = "incremental")]
+        mal_id: Option<i32>,
+        /// Skip MAL IDs whose characters were synced within --max-age-days
+        #[arg(long)]
+        incremental: bool,
+        /// How long synced characters stay fresh in incremental mode
+        #[arg(
+            long,
+            value_name = "DAYS",
+            default_value_t = 7,
+            requires = "incremental"
+        )]
+        max_age_days: u32,
+    },
+    /// Fetch relations between anime from Jikan for every anime with a MAL ID, or for a
+    /// single MAL ID
+    SyncRelations {
+        /// Only fetch the relations of the anime with this MAL ID
+        #[arg(long, conflicts_with = "incremental")]
+        mal_id: Option<i32>,
+        /// Skip MAL IDs whose relations were synced within --max-age-days
+        #[arg(long)]
+        incremental: bool,
+        /// How long synced relations stay fresh in incremental mode
+        #[arg(
+            long,
+            value_name = "DAYS",
+            default_value_t = 7,
+            requires = "incremental"
+        )]
+        max_age_days: u32,
+    },
+    /// Fetch cover colour, banner, tags, dates, season and scores from AniList for every
+    /// anime with an AniList ID
+    Enrich {
+        /// Skip anime enriched within --max-age-days
+        #[arg(long)]
+        incremental: bool,
+        /// How long enrichments stay fresh in incremental mode
+        #[arg(
+            long,
+            value_name = "DAYS",
+            default_value_t = 7,
+            requires = "incremental"
+        )]
+        max_age_days: u32,
+    },
+    #[command(flatten)]
+    Query(Query),
+}
+
+// Commands that only read the database, so they need neither the fetch settings nor the
+// rate limiter
+#[derive(Debug, Subcommand)]
+enum Query {
+    /// List the stored anime of the franchise of an anime in watch order
+    Franchise {
+        /// ID of any anime of the franchise
+        id: i32,
+    },
+    /// List every stored anime, or the anime matching every filter given
+    List {
+        /// Only list anime of this genre
+        #[arg(long)]
+        genre: Option<String>,
+        /// Only list anime made by this studio
+        #[arg(long)]
+        studio: Option<String>,
+        /// Only list anime with this producer
+        #[arg(long)]
+        producer: Option<String>,
+    },
+    /// Show a single stored anime
+    Show {
+        /// ID of the anime
+        id: i32,
+    },
+    /// Show every recorded change of an anime and its episodes, oldest first
+    History {
+        /// ID of the anime
+        id: i32,
+    },
+    /// List recent sync runs with what they did and a sample of their errors
+    Runs {
+        /// Number of runs to list, newest first
+        #[arg(long, default_value_t = 10)]
+        limit: u32,
+    },
+}
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let response = fetch_jikan_staff_response(21).await.unwrap();
-    let veec = response.data.iter();
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Query(query) => DatabaseConfig::load(cli.config.as_deref())
+            .and_then(|config| run_query(&Store::connect(&config)?, query)),
+        command => match Config::load(cli.config.as_deref()) {
+            Ok(config) => run(&config, command).await,
+            Err(e) => Err(e),
+        },
+    };
+
+    match result {
+        Ok(code) => code,
+        Err(e @ Error::Config(_)) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(EXIT_CONFIG)
+        }
+        Err(e @ Error::NotFound { .. }) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(EXIT_NOT_FOUND)
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(config: &Config, command: Command) -> Result<ExitCode, Error> {
+    let store = Store::connect(&config.database)?;
+    RateLimiter::global().configure_once(config);
+    let scheduler = Scheduler::new(config.sync_concurrency);
+    scheduler.cancel_on_ctrl_c();
+
+    match command {
+        Command::SyncIds => {
+            let run = start_sync_run(&store, &scheduler)?;
+            let result = add_new_anime_with_anime_id(config, store.pool(), &scheduler, &run).await;
+            finish_sync_run(&store, &run, &scheduler, result)?
+        }
+        Command::SyncDetails {
+            retry_failed,
+            restart,
+            stale_days,
+        } => {
+            let mode = if retry_failed {
+                SyncMode::RetryFailed
+            } else if restart {
+                SyncMode::Restart
+            } else if let Some(stale_days) = stale_days {
+                SyncMode::Stale(TimeDelta::days(i64::from(stale_days)))
+            } else {
+                SyncMode::Resume
+            };
+            let run = start_sync_run(&store, &scheduler)?;
+            let result =
+                store_anime_and_episode_data(config, store.pool(), mode, &scheduler, &run).await;
+            finish_sync_run(&store, &run, &scheduler, result)?
+        }
+        Command::SyncStaff {
+            mal_id: Some(mal_id),
+            ..
+        } => {
+            let anime_ids = store.anime_ids_by_mal_id(mal_id)?;
+            if anime_ids.is_empty() {
+                eprintln!("No anime with MAL ID {} in the database.", mal_id);
+                return Ok(ExitCode::from(EXIT_NOT_FOUND));
+            }
+
+            let stored = sync_single_mal_id(&store, &scheduler, STAFF_PIPELINE, mal_id, async {
+                let response = HianimeClient::new(config.clone()).staff(mal_id).await?;
+                let counts = store.save_staff(&response, &anime_ids)?;
+                Ok((response.data.len(), counts))
+            })
+            .await?;
+
+            println!("Stored {} staff for MAL ID {}.", stored, mal_id);
+        }
+        Command::SyncStaff {
+            mal_id: None,
+            incremental,
+            max_age_days,
+        } => {
+            let max_age = incremental.then(|| TimeDelta::days(i64::from(max_age_days)));
+            let run = start_sync_run(&store, &scheduler)?;
+            let result = store_staff_data(config, store.pool(), max_age, &scheduler, &run).await;
+            finish_sync_run(&store, &run, &scheduler, result)?
+        }
+        Command::SyncCharacters {
+            mal_id: Some(mal_id),
+            ..
+        } => {
+            let anime_ids = store.anime_ids_by_mal_id(mal_id)?;
+            if anime_ids.is_empty() {
+                eprintln!("No anime with MAL ID {} in the database.", mal_id);
+                return Ok(ExitCode::from(EXIT_NOT_FOUND));
+            }
 
-    for i in veec {
-        insert_into_anime_staff(i, 21);
-        insert_or_update_staff(i);
+            let stored =
+                sync_single_mal_id(&store, &scheduler, CHARACTERS_PIPELINE, mal_id, async {
+                    let response = HianimeClient::new(config.clone())
+                        .characters(mal_id)
+                        .await?;
+                    let counts = store.save_characters(&response, &anime_ids)?;
+                    Ok((response.data.len(), counts))
+                })
+                .await?;
+
+            println!("Stored {} characters for MAL ID {}.", stored, mal_id);
+        }
+        Command::SyncCharacters {
+            mal_id: None,
+            incremental,
+            max_age_days,
+        } => {
+            let max_age = incremental.then(|| TimeDelta::days(i64::from(max_age_days)));
+            let run = start_sync_run(&store, &scheduler)?;
+            let result =
+                store_character_data(config, store.pool(), max_age, &scheduler, &run).await;
+            finish_sync_run(&store, &run, &scheduler, result)?
+        }
+        Command::SyncRelations {
+            mal_id: Some(mal_id),
+            ..
+        } => {
+            let anime_ids = store.anime_ids_by_mal_id(mal_id)?;
+            if anime_ids.is_empty() {
+                eprintln!("No anime with MAL ID {} in the database.", mal_id);
+                return Ok(ExitCode::from(EXIT_NOT_FOUND));
+            }
+
+            let stored =
+                sync_single_mal_id(&store, &scheduler, RELATIONS_PIPELINE, mal_id, async {
+                    let response = HianimeClient::new(config.clone()).relations(mal_id).await?;
+                    let counts = store.save_relations(&response, &anime_ids)?;
+                    Ok((response.data.len(), counts))
+                })
+                .await?;
+
+            println!("Stored {} relations for MAL ID {}.", stored, mal_id);
+        }
+        Command::SyncRelations {
+            mal_id: None,
+            incremental,
+            max_age_days,
+        } => {
+            let max_age = incremental.then(|| TimeDelta::days(i64::from(max_age_days)));
+            let run = start_sync_run(&store, &scheduler)?;
+            let result = store_relation_data(config, store.pool(), max_age, &scheduler, &run).await;
+            finish_sync_run(&store, &run, &scheduler, result)?
+        }
+        Command::Enrich {
+            incremental,
+            max_age_days,
+        } => {
+            let max_age = incremental.then(|| TimeDelta::days(i64::from(max_age_days)));
+            let run = start_sync_run(&store, &scheduler)?;
+            let result =
+                store_enrichment_data(config, store.pool(), max_age, &scheduler, &run).await;
+            finish_sync_run(&store, &run, &scheduler, result)?
+        }
+        Command::Query(query) => return run_query(&store, query),
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+// Answer a command that only reads the database
+fn run_query(store: &Store, query: Query) -> Result<ExitCode, Error> {
+    match query {
+        Query::Franchise { id } => {
+            let watch_order = store.watch_order(id)?;
+            if watch_order.is_empty() {
+                eprintln!("No anime with ID {} in the database.", id);
+                return Ok(ExitCode::from(EXIT_NOT_FOUND));
+            }
+            for anime in watch_order {
+                println!("{}\t{}\t{}", anime.id, anime.mal_id, anime.title);
+            }
+        }
+        Query::List {
+            genre,
+            studio,
+            producer,
+        } => {
+            let filter = AnimeFilter {
+                genre,
+                studio,
+                producer,
+            };
+            for anime in store.anime_matching(&filter)? {
+                println!("{}\t{}\t{}", anime.id, anime.mal_id, anime.title);
+            }
+        }
+        Query::Show { id } => match store.anime(id)? {
+            Some(anime) => {
+                println!("{:#?}", anime);
+                let genres: Vec<_> = store.genres(id)?.into_iter().map(|g| g.name).collect();
+                let studios: Vec<_> = store.studios(id)?.into_iter().map(|s| s.name).collect();
+                let producers: Vec<_> = store.producers(id)?.into_iter().map(|p| p.name).collect();
+                println!("genres: {}", genres.join(", "));
+                println!("studios: {}", studios.join(", "));
+                println!("producers: {}", producers.join(", "));
+                if let Some(enrichment) = store.enrichment(id)? {
+                    println!("{:#?}", enrichment);
+                }
+            }
+            None => {
+                eprintln!("No anime with ID {} in the database.", id);
+                return Ok(ExitCode::from(EXIT_NOT_FOUND));
+            }
+        },
+        Query::History { id } => {
+            if store.anime(id)?.is_none() {
+                eprintln!("No anime with ID {} in the database.", id);
+                return Ok(ExitCode::from(EXIT_NOT_FOUND));
+            }
+            for change in store.history_for(id)? {
+                println!(
+                    "{}\t{}\tanime\t{}\t{} -> {}",
+                    change.changed_at,
+                    sync_run_label(change.sync_run_id),
+                    change.field,
+                    change.old_value.as_deref().unwrap_or("NULL"),
+                    change.new_value.as_deref().unwrap_or("NULL"),
+                );
+            }
+            for change in store.episode_history_for(id)? {
+                println!(
+                    "{}\t{}\t{}\t{}\t{} -> {}",
+                    change.changed_at,
+                    sync_run_label(change.sync_run_id),
+                    change.episode_id,
+                    change.field,
+                    change.old_value.as_deref().unwrap_or("NULL"),
+                    change.new_value.as_deref().unwrap_or("NULL"),
+                );
+            }
+        }
+        Query::Runs { limit } => {
+            for run in store.recent_sync_runs(i64::from(limit))? {
+                let duration = match run.finished_at {
+                    Some(finished_at) => {
+                        format!("{}s", (finished_at - run.started_at).num_seconds())
+                    }
+                    None => String::from("-"),
+                };
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    run.id, run.status, run.started_at, duration, run.command
+                );
+                if let Some(error) = &run.error {
+                    println!("\terror: {}", error);
+                }
+                for count in store.sync_run_counts(run.id)? {
+                    println!(
+                        "\t{}: {} inserted, {} updated, {} unchanged, {} deleted, {} failed",
+                        count.entity,
+                        count.inserted,
+                        count.updated,
+                        count.unchanged,
+                        count.deleted,
+                        count.failed
+                    );
+                }
+                for error in store.sync_run_errors(run.id)? {
+                    println!(
+                        "\t{} {} failed: {}",
+                        error.entity, error.item_key, error.message
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+// Arguments the program was started with, recorded as the command of a sync run
+fn command_line() -> String {
+    env::args().skip(1).collect::<Vec<_>>().join(" ")
+}
+
+// Open a run in the ledger, to be closed as cancelled should a second Ctrl-C abort the
+// process before `finish_sync_run`
+fn start_sync_run(store: &Store, scheduler: &Scheduler) -> Result<RunRecorder, Error> {
+    let run = store.start_sync_run(&command_line())?;
+    let (store, aborted) = (store.clone(), run.clone());
+    scheduler.on_abort(move || {
+        if let Err(e) = store.abort_sync_run(&aborted) {
+            eprintln!("Failed to record sync run {}: {}", aborted.id(), e);
+        }
+    });
+    Ok(run)
+}
+
+// Record the outcome of a pipeline in the sync run ledger, then pass it on
+fn finish_sync_run(
+    store: &Store,
+    run: &RunRecorder,
+    scheduler: &Scheduler,
+    result: Result<(), Error>,
+) -> Result<(), Error> {
+    if let Err(e) = store.finish_sync_run(run, &result, scheduler.is_cancelled()) {
+        eprintln!("Failed to record sync run {}: {}", run.id(), e);
     }
+    result
+}
+
+// Sync a single MAL ID as a run of its own. `sync` returns how many items it stored and
+// the counts of what it wrote
+async fn sync_single_mal_id(
+    store: &Store,
+    scheduler: &Scheduler,
+    pipeline: &'static str,
+    mal_id: i32,
+    sync: impl Future<Output = Result<(usize, RunCounts), Error>>,
+) -> Result<usize, Error> {
+    let run = start_sync_run(store, scheduler)?;
+    let (stored, result) = match sync.await {
+        Ok((stored, counts)) => {
+            run.record(&counts);
+            (stored, Ok(()))
+        }
+        Err(e) => {
+            run.record_failure(pipeline, &mal_id.to_string(), &e.to_string());
+            (0, Err(e))
+        }
+    };
+    finish_sync_run(store, &run, scheduler, result)?;
+    Ok(stored)
+}
 
-    // add_new_anime_with_anime_id().await;
-    // store_anime_and_episode_data().await;
-    // let res = fetch_anime_details("jujutsu-kaisen-2nd-season-18413".to_string()).await;
-    // println!("{:?}", res);
-    // let anime_list = load_all_anime_ids();
-    // println!("{:?}", anime_list);
-    // store_anime_and_episode_data().await;
-    // let new_anime = Anime {
-    //     id: 18075,
-    //     title: "Overlord IV".to_string(),
-    //     mal_id: 48895,
-    //     al_id: 133844,
-    //     japanese_title: Some("".to_string()),
-    //     image: "https://cdn.noitatnemucod.net/thumbnail/300x400/100/ef1d1028c6c177587805651b78282a6.jpg".to_string(),
-    //     description: "E-Rantel, the capital city of the newly established Sorcerer Kingdom, suffers from a dire shortage of goods. Once a prosperous city known for its trade, it now faces a crisis due to its caution—or even fear—of its king, Ainz Ooal Gown. To make amends, Ainz sends Albedo to the city as a diplomatic envoy.\n\nMeanwhile, the cardinals of the Slane Theocracy discuss how to retaliate against Ainz after his attack crippled the Re-Estize Kingdom's army, plotting for the Baharuth Empire to take over the Sorcerer Kingdom. However, when Emperor Jircniv Rune Farlord El Nix arranges a meeting with the Theocracy's messengers at a colosseum, he is confronted by none other than Ainz himself.\n\nWith their secret gathering now out in the open, the emperor and his guests learn that Ainz has challenged the Warrior King, the empire's greatest fighter, to a duel. With Ainz's motivations beyond his comprehension, Jircniv can do nothing but watch as humanity's future changes before his very eyes.".to_string(),
-    //     type_: "TV".to_string(),
-    //     sub_or_dub: "both".to_string()
-    // };
-    //
-    // add_new_anime(new_anime, &mut connection)?;
-    // load_all_anime(&mut connection);
-
-    Ok(())
+// Sync run of a change, `-` for changes made outside a sync
+fn sync_run_label(sync_run_id: Option<i64>) -> String {
+    sync_run_id.map_or_else(|| String::from("-"), |id| id.to_string())
 }
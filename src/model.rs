@@ -1,7 +1,13 @@
-use diesel::{prelude::*, sql_types::Array};
+use chrono::{DateTime, NaiveDate, Utc};
+use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::schema::{anime, anime_id, anime_staff, episodes, staff};
+use crate::anime_fields::{AgeRating, AiringStatus, AudioAvailability, Category, Season};
+use crate::schema::{
+    anime, anime_characters, anime_enrichment, anime_enrichment_tags, anime_history, anime_id,
+    anime_relations, anime_staff, characters, episode_history, episodes, genres, producers, staff,
+    studios, sync_run_counts, sync_run_errors, sync_runs, sync_state, voice_actors,
+};
 
 #[derive(Queryable, Insertable, Selectable, Debug)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
@@ -15,21 +21,27 @@ pub struct Anime {
     pub japanese_title: Option<String>,
     pub synonyms: Option<String>,
     pub image: String,
-    pub category: String,
-    pub rating: String,
+    pub category: Option<Category>,
+    pub rating: Option<AgeRating>,
     pub quality: String,
-    pub duration: String,
-    pub premiered: String,
-    pub aired: String,
-    pub status: String,
-    pub mal_score: String,
+    pub status: Option<AiringStatus>,
+    pub mal_score: Option<f32>,
     pub studios: String,
     pub producers: String,
     pub genres: String,
     pub sub_episodes: i32,
     pub dub_episodes: i32,
     pub total_episodes: i32,
-    pub sub_or_dub: String,
+    pub sub_or_dub: Option<AudioAvailability>,
+    // As fetched, kept next to the parsed columns when they don't parse
+    pub duration: Option<String>,
+    pub premiered: Option<String>,
+    pub aired: Option<String>,
+    pub duration_minutes: Option<i32>,
+    pub premiered_season: Option<Season>,
+    pub premiered_year: Option<i32>,
+    pub aired_start: Option<NaiveDate>,
+    pub aired_end: Option<NaiveDate>,
 }
 
 #[derive(Queryable, Insertable, Selectable, Debug, Serialize, Deserialize)]
@@ -69,3 +81,174 @@ pub struct AnimeStaff {
     pub staff_id: i32,
     pub positions: Vec<Option<String>>,
 }
+
+#[derive(Queryable, Insertable, Selectable, Debug)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = characters)]
+pub struct Character {
+    pub mal_id: i32,
+    pub name: String,
+    pub mal_url: String,
+    pub image: String,
+}
+
+#[derive(Queryable, Insertable, Selectable, Debug)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = anime_characters)]
+pub struct AnimeCharacter {
+    pub anime_id: i32,
+    pub character_id: i32,
+    // Main or Supporting
+    pub role: String,
+}
+
+// A staff voicing a character of an anime
+#[derive(Queryable, Insertable, Selectable, Debug)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = voice_actors)]
+pub struct VoiceActor {
+    pub anime_id: i32,
+    pub character_id: i32,
+    pub staff_id: i32,
+    pub language: String,
+}
+
+// A relation such as Sequel or Side Story from an anime to another, by MAL ID
+#[derive(Queryable, Insertable, Selectable, Debug)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = anime_relations)]
+pub struct AnimeRelation {
+    pub anime_id: i32,
+    pub related_mal_id: i32,
+    pub relation: String,
+}
+
+// Fields fetched from AniList for an anime
+#[derive(Queryable, Insertable, Selectable, Debug)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = anime_enrichment)]
+pub struct AnimeEnrichment {
+    pub anime_id: i32,
+    pub al_id: i32,
+    pub cover_color: Option<String>,
+    pub banner_image: Option<String>,
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    pub season: Option<String>,
+    pub season_year: Option<i32>,
+    pub popularity: Option<i32>,
+    pub average_score: Option<i32>,
+    pub fetched_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Insertable, Selectable, Debug)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = anime_enrichment_tags)]
+pub struct AnimeEnrichmentTag {
+    pub anime_id: i32,
+    pub name: String,
+    // Percentage of AniList users who agree the tag applies
+    pub rank: Option<i32>,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = genres)]
+pub struct Genre {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = studios)]
+pub struct Studio {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = producers)]
+pub struct Producer {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = sync_state)]
+pub struct SyncState {
+    pub pipeline: String,
+    pub item_key: String,
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// A field of an anime changed by an upsert
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = anime_history)]
+pub struct AnimeHistory {
+    pub id: i64,
+    pub anime_id: i32,
+    pub sync_run_id: Option<i64>,
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub changed_at: DateTime<Utc>,
+}
+
+// A field of an episode changed by an upsert
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = episode_history)]
+pub struct EpisodeHistory {
+    pub id: i64,
+    pub episode_id: String,
+    pub anime_id: i32,
+    pub sync_run_id: Option<i64>,
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub changed_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = sync_runs)]
+pub struct SyncRun {
+    pub id: i64,
+    pub command: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+// What a sync run did to one entity, such as `anime` or `staff`
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = sync_run_counts)]
+pub struct SyncRunCount {
+    pub sync_run_id: i64,
+    pub entity: String,
+    pub inserted: i64,
+    pub updated: i64,
+    pub unchanged: i64,
+    pub failed: i64,
+    pub deleted: i64,
+}
+
+#[derive(Queryable, Selectable, Debug)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(table_name = sync_run_errors)]
+pub struct SyncRunError {
+    pub id: i64,
+    pub sync_run_id: i64,
+    pub entity: String,
+    pub item_key: String,
+    pub message: String,
+}
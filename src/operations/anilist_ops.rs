@@ -0,0 +1,374 @@
+// anilist_ops.rs
+
+use crate::config::Config;
+use crate::db::PgPool;
+use crate::error::Error;
+use crate::http::{self, ClientFactory};
+use crate::model::{AnimeEnrichment, AnimeEnrichmentTag};
+use crate::operations::history_ops::{inserted_row, upsert_outcome};
+use crate::operations::sync_run_ops::{RunCounts, RunRecorder, ENRICHMENT_ENTITY};
+use crate::retry::RetryPolicy;
+use crate::scheduler::Scheduler;
+use crate::schema::{anime, anime_enrichment, anime_enrichment_tags};
+use chrono::{NaiveDate, TimeDelta, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::upsert::excluded;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{BTreeMap, HashSet};
+
+// AniList pages hold at most 50 media, so one request covers 50 IDs
+pub const ANILIST_BATCH_SIZE: usize = 50;
+
+const MEDIA_QUERY: &str = "
+query ($ids: [Int], $perPage: Int) {
+  Page(perPage: $perPage) {
+    media(id_in: $ids, type: ANIME) {
+      id
+      coverImage { color }
+      bannerImage
+      tags { name rank }
+      startDate { year month day }
+      endDate { year month day }
+      season
+      seasonYear
+      popularity
+      averageScore
+    }
+  }
+}";
+
+#[derive(Debug, Deserialize)]
+struct GraphQlResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphQlError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphQlError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PageData {
+    #[serde(rename = "Page")]
+    page: MediaPage,
+}
+
+#[derive(Debug, Deserialize)]
+struct MediaPage {
+    media: Vec<AniListMedia>,
+}
+
+// The fields of an AniList media the anime table lacks
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AniListMedia {
+    pub id: i32,
+    pub cover_image: Option<CoverImage>,
+    pub banner_image: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<MediaTag>,
+    pub start_date: Option<FuzzyDate>,
+    pub end_date: Option<FuzzyDate>,
+    pub season: Option<String>,
+    pub season_year: Option<i32>,
+    pub popularity: Option<i32>,
+    pub average_score: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CoverImage {
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MediaTag {
+    pub name: String,
+    pub rank: Option<i32>,
+}
+
+// AniList dates may lack the day or the month, or be missing entirely
+#[derive(Debug, Deserialize)]
+pub struct FuzzyDate {
+    pub year: Option<i32>,
+    pub month: Option<u32>,
+    pub day: Option<u32>,
+}
+
+impl FuzzyDate {
+    // The date, or the first of its month or year when only those are known. There is no
+    // date without a year
+    pub fn to_date(&self) -> Option<NaiveDate> {
+        let day = self.month.and(self.day).unwrap_or(1);
+        NaiveDate::from_ymd_opt(self.year?, self.month.unwrap_or(1), day)
+    }
+}
+
+impl AniListMedia {
+    pub fn to_enrichment(&self, anime_table_id: i32) -> AnimeEnrichment {
+        AnimeEnrichment {
+            anime_id: anime_table_id,
+            al_id: self.id,
+            cover_color: self
+                .cover_image
+                .as_ref()
+                .and_then(|cover| cover.color.clone()),
+            banner_image: self.banner_image.clone(),
+            start_date: self.start_date.as_ref().and_then(FuzzyDate::to_date),
+            end_date: self.end_date.as_ref().and_then(FuzzyDate::to_date),
+            season: self.season.clone(),
+            season_year: self.season_year,
+            popularity: self.popularity,
+            average_score: self.average_score,
+            fetched_at: Utc::now(),
+        }
+    }
+
+    pub fn to_tags(&self, anime_table_id: i32) -> Vec<AnimeEnrichmentTag> {
+        let mut seen = HashSet::new();
+        self.tags
+            .iter()
+            .filter(|tag| seen.insert(&tag.name))
+            .map(|tag| AnimeEnrichmentTag {
+                anime_id: anime_table_id,
+                name: tag.name.clone(),
+                rank: tag.rank,
+            })
+            .collect()
+    }
+}
+
+// Client for the AniList GraphQL API
+#[derive(Debug, Clone)]
+pub struct AniListClient {
+    url: String,
+    retry: RetryPolicy,
+}
+
+impl AniListClient {
+    pub fn new(url: impl Into<String>, retry: RetryPolicy) -> Self {
+        AniListClient {
+            url: url.into(),
+            retry,
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        AniListClient::new(
+            config.anilist_api_url.clone(),
+            RetryPolicy::from_config(config),
+        )
+    }
+
+    // Fetch the media of up to ANILIST_BATCH_SIZE AniList IDs in one request,
+    // IDs AniList does not know are left out of the result
+    pub async fn media(&self, al_ids: &[i32]) -> Result<Vec<AniListMedia>, Error> {
+        let body = json!({
+            "query": MEDIA_QUERY,
+            "variables": { "ids": al_ids, "perPage": ANILIST_BATCH_SIZE },
+        });
+        let client = ClientFactory::global().api()?;
+        let response: GraphQlResponse<PageData> = self
+            .retry
+            .run(|| async {
+                let response = http::post_json(&client, &self.url, &body).await?;
+                http::read_json(&self.url, response).await
+            })
+            .await?;
+
+        if !response.errors.is_empty() {
+            let messages: Vec<_> = response.errors.into_iter().map(|e| e.message).collect();
+            return Err(Error::Parse {
+                url: self.url.clone(),
+                message: messages.join("; "),
+            });
+        }
+        match response.data {
+            Some(data) => Ok(data.page.media),
+            None => Err(Error::Parse {
+                url: self.url.clone(),
+                message: String::from("response has no data"),
+            }),
+        }
+    }
+}
+
+// Store the enrichment of every anime sharing an AniList ID, replacing earlier ones
+pub fn save_enrichment(
+    media: &AniListMedia,
+    anime_ids: &[i32],
+    connection: &mut PgConnection,
+) -> Result<RunCounts, DieselError> {
+    connection.transaction(|connection| {
+        let mut counts = RunCounts::default();
+        for anime_id in anime_ids {
+            // `fetched_at` changes on every fetch, so an existing row is always updated
+            let inserted = diesel::insert_into(anime_enrichment::table)
+                .values(&media.to_enrichment(*anime_id))
+                .on_conflict(anime_enrichment::anime_id)
+                .do_update()
+                .set((
+                    anime_enrichment::al_id.eq(excluded(anime_enrichment::al_id)),
+                    anime_enrichment::cover_color.eq(excluded(anime_enrichment::cover_color)),
+                    anime_enrichment::banner_image.eq(excluded(anime_enrichment::banner_image)),
+                    anime_enrichment::start_date.eq(excluded(anime_enrichment::start_date)),
+                    anime_enrichment::end_date.eq(excluded(anime_enrichment::end_date)),
+                    anime_enrichment::season.eq(excluded(anime_enrichment::season)),
+                    anime_enrichment::season_year.eq(excluded(anime_enrichment::season_year)),
+                    anime_enrichment::popularity.eq(excluded(anime_enrichment::popularity)),
+                    anime_enrichment::average_score.eq(excluded(anime_enrichment::average_score)),
+                    anime_enrichment::fetched_at.eq(excluded(anime_enrichment::fetched_at)),
+                ))
+                .returning(inserted_row())
+                .get_result::<bool>(connection)?;
+            counts.add(ENRICHMENT_ENTITY, upsert_outcome(Some(inserted)));
+
+            diesel::delete(
+                anime_enrichment_tags::table.filter(anime_enrichment_tags::anime_id.eq(anime_id)),
+            )
+            .execute(connection)?;
+            diesel::insert_into(anime_enrichment_tags::table)
+                .values(&media.to_tags(*anime_id))
+                .execute(connection)?;
+        }
+        Ok(counts)
+    })
+}
+
+pub fn load_enrichment(
+    anime_table_id: i32,
+    connection: &mut PgConnection,
+) -> Result<Option<AnimeEnrichment>, DieselError> {
+    anime_enrichment::table
+        .find(anime_table_id)
+        .select(AnimeEnrichment::as_select())
+        .first(connection)
+        .optional()
+}
+
+// Load the AniList ID and ID of every anime with a known AniList ID. With `max_age`,
+// anime enriched more recently than that are left out
+pub fn load_anime_al_ids(
+    max_age: Option<TimeDelta>,
+    connection: &mut PgConnection,
+) -> Result<Vec<(i32, i32)>, DieselError> {
+    // The api reports a missing AniList ID as 0
+    let mut query = anime::table
+        .left_join(anime_enrichment::table)
+        .filter(anime::al_id.gt(0))
+        .order((anime::al_id.asc(), anime::id.asc()))
+        .select((anime::al_id, anime::id))
+        .into_boxed();
+    if let Some(max_age) = max_age {
+        query = query.filter(
+            anime_enrichment::fetched_at
+                .is_null()
+                .or(anime_enrichment::fetched_at.le(Utc::now() - max_age)),
+        );
+    }
+    query.load(connection)
+}
+
+// Enrich every anime with an AniList ID, batching ANILIST_BATCH_SIZE IDs per request.
+// With `max_age`, anime enriched more recently than that are skipped
+pub async fn store_enrichment_data(
+    config: &Config,
+    pool: &PgPool,
+    max_age: Option<TimeDelta>,
+    scheduler: &Scheduler,
+    run: &RunRecorder,
+) -> Result<(), Error> {
+    // Anime sharing an AniList ID share its media, so each ID is fetched once
+    let mut al_ids: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+    {
+        let mut connection = pool.get()?;
+        for (al_id, anime_id) in load_anime_al_ids(max_age, &mut connection)? {
+            al_ids.entry(al_id).or_default().push(anime_id);
+        }
+    }
+    let al_ids: Vec<_> = al_ids.into_iter().collect();
+    let batches: Vec<Vec<(i32, Vec<i32>)>> = al_ids
+        .chunks(ANILIST_BATCH_SIZE)
+        .map(<[_]>::to_vec)
+        .collect();
+    println!(
+        "{} AniList IDs to enrich in {} requests.",
+        al_ids.len(),
+        batches.len()
+    );
+
+    let client = AniListClient::from_config(config);
+    let job_pool = pool.clone();
+    let job_run = run.clone();
+
+    let results = scheduler
+        .run(batches, move |batch| {
+            let client = client.clone();
+            let pool = job_pool.clone();
+            let run = job_run.clone();
+            async move {
+                let ids: Vec<i32> = batch.iter().map(|(al_id, _)| *al_id).collect();
+                let media = match client.media(&ids).await {
+                    Ok(media) => media,
+                    Err(e) => {
+                        let key = ids.iter().map(i32::to_string).collect::<Vec<_>>().join(",");
+                        run.record_failure(ENRICHMENT_ENTITY, &key, &e.to_string());
+                        return Err(e);
+                    }
+                };
+
+                let mut connection = pool.get()?;
+                let anime_ids: BTreeMap<i32, Vec<i32>> = batch.into_iter().collect();
+                let mut saved = 0;
+                for entry in &media {
+                    let Some(anime_ids) = anime_ids.get(&entry.id) else {
+                        continue;
+                    };
+                    match save_enrichment(entry, anime_ids, &mut connection) {
+                        Ok(counts) => {
+                            run.record(&counts);
+                            saved += 1;
+                        }
+                        Err(e) => {
+                            eprintln!("Failed to save AniList media {}: {}", entry.id, e);
+                            run.record_failure(
+                                ENRICHMENT_ENTITY,
+                                &entry.id.to_string(),
+                                &e.to_string(),
+                            );
+                        }
+                    }
+                }
+                Ok::<_, Error>((saved, ids.len() - media.len()))
+            }
+        })
+        .await?;
+
+    let (mut enriched, mut missing, mut failed) = (0, 0, 0);
+    for result in results {
+        match result {
+            Ok((found, not_found)) => {
+                enriched += found;
+                missing += not_found;
+            }
+            Err(e) => {
+                eprintln!("Failed to fetch AniList batch: {}", e);
+                failed += 1;
+            }
+        }
+    }
+
+    if scheduler.is_cancelled() {
+        println!("AniList enrichment cancelled.");
+    }
+    println!(
+        "AniList enrichment Complete. {} enriched, {} unknown to AniList, {} failed requests.",
+        enriched, missing, failed
+    );
+
+    Ok(())
+}
@@ -3,125 +3,109 @@
 extern crate reqwest;
 extern crate serde;
 
-use crate::db::establish_connection;
+use crate::config::Config;
+use crate::db::PgPool;
+use crate::error::Error;
+use crate::http::{self, ClientFactory};
 use crate::model::{Anime, AnimeID};
 use crate::operations::atoz_ops::get_last_page_no_of_atoz_list;
-use crate::schema::{anime, anime_id};
+use crate::operations::history_ops::{
+    anime_changes, record_anime_changes, UpsertCounts, UpsertOutcome,
+};
+use crate::operations::sync_run_ops::{RunCounts, RunRecorder, ANIME_IDS_ENTITY};
+use crate::retry::RetryPolicy;
+use crate::scheduler::Scheduler;
+use crate::schema::anime;
+use chrono::{TimeDelta, Utc};
+use diesel::dsl::now;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use diesel::result::Error as DieselError;
-use dotenvy::dotenv;
-use reqwest::Error as ReqwestError;
+use diesel::sql_types::Integer;
+use diesel::upsert::excluded;
 use serde::Deserialize;
-use std::boxed::Box;
-use std::env;
-use std::error::Error as StdError;
-use std::fmt;
-use std::fmt::Formatter;
-use tokio::task::JoinError;
-use tokio::task::JoinHandle;
-
-// Define CustomError for handling multiple error types
-#[derive(Debug)]
-pub enum CustomError {
-    JoinError(JoinError),
-    DieselError(DieselError),
-    ReqwestError(ReqwestError),
-    NoProxiesAvailable,
-    FailedToFetchAfterRetries,
-    Other(String),
-}
 
-// Implement `fmt::Display` for `CustomError`
-impl fmt::Display for CustomError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        match self {
-            CustomError::JoinError(err) => write!(f, "Join Error: {}", err),
-            CustomError::DieselError(err) => write!(f, "Diesel Error: {}", err),
-            CustomError::ReqwestError(err) => write!(f, "Reqwest Error: {}", err),
-            CustomError::NoProxiesAvailable => write!(f, "No proxies available"),
-            CustomError::FailedToFetchAfterRetries => write!(f, "Failed to fetch after retries"),
-            CustomError::Other(msg) => write!(f, "{}", msg),
-        }
-    }
-}
+// First key of the advisory locks taken on anime IDs, so they don't collide with others
+const ANIME_LOCK_CLASS: i32 = 1;
 
-// Implement conversions from specific error types to CustomError
-impl From<JoinError> for CustomError {
-    fn from(err: JoinError) -> Self {
-        CustomError::JoinError(err)
-    }
-}
-
-impl From<DieselError> for CustomError {
-    fn from(err: DieselError) -> Self {
-        CustomError::DieselError(err)
-    }
-}
-
-impl From<ReqwestError> for CustomError {
-    fn from(err: ReqwestError) -> Self {
-        CustomError::ReqwestError(err)
-    }
-}
-
-// Implement `From<Box<dyn StdError>>` for `CustomError`
-impl From<Box<dyn StdError>> for CustomError {
-    fn from(err: Box<dyn StdError>) -> Self {
-        CustomError::Other(err.to_string())
-    }
-}
-
-// Implement `StdError` for `CustomError`
-impl StdError for CustomError {}
-
-// Function to add a new anime to the database
-pub fn add_new_anime(new_anime: Anime) -> Result<(), DieselError> {
-    let mut connection = establish_connection();
+// Function to add a new anime to the database, recording every field an update changes.
+// Must run in a transaction, which holds the lock on the anime ID until it ends
+pub fn add_new_anime(
+    new_anime: &Anime,
+    sync_run_id: Option<i64>,
+    connection: &mut PgConnection,
+) -> Result<UpsertOutcome, DieselError> {
     use crate::schema::anime::dsl::*;
 
-    // Check if the anime already exists
-    let anime_exists = diesel::select(diesel::dsl::exists(anime.filter(id.eq(&new_anime.id))))
-        .get_result(&mut connection)?;
-
-    if anime_exists {
-        // Update existing anime
-        diesel::update(anime.find(new_anime.id))
-            .set((
-                title.eq(new_anime.title),
-                description.eq(new_anime.description),
-                mal_id.eq(new_anime.mal_id),
-                al_id.eq(new_anime.al_id),
-                japanese_title.eq(new_anime.japanese_title),
-                synonyms.eq(new_anime.synonyms),
-                image.eq(new_anime.image),
-                category.eq(new_anime.category),
-                rating.eq(new_anime.rating),
-                quality.eq(new_anime.quality),
-                duration.eq(new_anime.duration),
-                premiered.eq(new_anime.premiered),
-                aired.eq(new_anime.aired),
-                status.eq(new_anime.status),
-                mal_score.eq(new_anime.mal_score),
-                studios.eq(new_anime.studios),
-                producers.eq(new_anime.producers),
-                genres.eq(new_anime.genres),
-                sub_episodes.eq(new_anime.sub_episodes),
-                dub_episodes.eq(new_anime.dub_episodes),
-                total_episodes.eq(new_anime.total_episodes),
-                sub_or_dub.eq(new_anime.sub_or_dub),
-            ))
-            .execute(&mut connection)?;
-    } else {
-        // Insert new anime
-        diesel::insert_into(anime)
-            .values(&new_anime)
-            .execute(&mut connection)?;
-    }
+    // Lock the ID rather than the row, which doesn't exist yet for a new anime, so
+    // concurrent writers diff against the same version and only one of them inserts
+    diesel::sql_query("SELECT pg_advisory_xact_lock($1, $2)")
+        .bind::<Integer, _>(ANIME_LOCK_CLASS)
+        .bind::<Integer, _>(new_anime.id)
+        .execute(connection)?;
+    let stored = anime
+        .find(new_anime.id)
+        .select(Anime::as_select())
+        .first::<Anime>(connection)
+        .optional()?;
+    let outcome = match stored {
+        Some(stored) => {
+            let changes = anime_changes(&stored, new_anime);
+            if changes.is_empty() {
+                diesel::update(anime.find(new_anime.id))
+                    .set(last_fetched_at.eq(now))
+                    .execute(connection)?;
+                return Ok(UpsertOutcome::Unchanged);
+            }
+            record_anime_changes(new_anime.id, sync_run_id, &changes, connection)?;
+            UpsertOutcome::Updated
+        }
+        None => UpsertOutcome::Inserted,
+    };
 
-    Ok(())
+    // Insert the anime, or update every column when the ID already exists
+    diesel::insert_into(anime)
+        .values(new_anime)
+        .on_conflict(id)
+        .do_update()
+        .set((
+            title.eq(excluded(title)),
+            description.eq(excluded(description)),
+            mal_id.eq(excluded(mal_id)),
+            al_id.eq(excluded(al_id)),
+            japanese_title.eq(excluded(japanese_title)),
+            synonyms.eq(excluded(synonyms)),
+            image.eq(excluded(image)),
+            category.eq(excluded(category)),
+            rating.eq(excluded(rating)),
+            quality.eq(excluded(quality)),
+            status.eq(excluded(status)),
+            mal_score.eq(excluded(mal_score)),
+            studios.eq(excluded(studios)),
+            producers.eq(excluded(producers)),
+            genres.eq(excluded(genres)),
+            sub_episodes.eq(excluded(sub_episodes)),
+            dub_episodes.eq(excluded(dub_episodes)),
+            total_episodes.eq(excluded(total_episodes)),
+            sub_or_dub.eq(excluded(sub_or_dub)),
+            duration.eq(excluded(duration)),
+            premiered.eq(excluded(premiered)),
+            aired.eq(excluded(aired)),
+            duration_minutes.eq(excluded(duration_minutes)),
+            premiered_season.eq(excluded(premiered_season)),
+            premiered_year.eq(excluded(premiered_year)),
+            aired_start.eq(excluded(aired_start)),
+            aired_end.eq(excluded(aired_end)),
+            updated_at.eq(now),
+            last_fetched_at.eq(now),
+        ))
+        .execute(connection)?;
+
+    Ok(outcome)
 }
-// Function to delete an anime by its ID
+
+// Function to delete an anime by its ID, the database deletes its episodes and every other
+// row linked to it
 pub fn delete_anime_by_id(
     anime_id: i32,
     connection: &mut PgConnection,
@@ -133,55 +117,128 @@ pub fn delete_anime_by_id(
 }
 
 // Function to load all anime from the database
-pub fn load_all_anime() -> Result<(), DieselError> {
-    let mut connection = establish_connection();
+pub fn load_all_anime(connection: &mut PgConnection) -> Result<Vec<Anime>, DieselError> {
     use crate::schema::anime::dsl::*;
     let results = anime
         .select(Anime::as_select())
-        .load::<Anime>(&mut connection)?;
-    println!("{:?}", results);
-    Ok(())
+        .order(id.asc())
+        .load::<Anime>(connection)?;
+    Ok(results)
+}
+
+// Function to load a single anime by its ID
+pub fn load_anime_by_id(
+    anime_table_id: i32,
+    connection: &mut PgConnection,
+) -> Result<Option<Anime>, DieselError> {
+    use crate::schema::anime::dsl::*;
+    let result = anime
+        .find(anime_table_id)
+        .select(Anime::as_select())
+        .first::<Anime>(connection)
+        .optional()?;
+    Ok(result)
+}
+
+// Function to find the IDs of every anime sharing the given MAL ID
+pub fn find_anime_ids_by_mal_id(
+    anime_mal_id: i32,
+    connection: &mut PgConnection,
+) -> Result<Vec<i32>, DieselError> {
+    use crate::schema::anime::dsl::*;
+    let results = anime
+        .filter(mal_id.eq(anime_mal_id))
+        .select(id)
+        .load::<i32>(connection)?;
+    Ok(results)
+}
+
+// Function to load the MAL ID and ID of every anime with a known MAL ID
+pub fn load_anime_mal_ids(connection: &mut PgConnection) -> Result<Vec<(i32, i32)>, DieselError> {
+    use crate::schema::anime::dsl::*;
+    // The api reports a missing MAL ID as 0
+    let results = anime
+        .filter(mal_id.gt(0))
+        .order((mal_id.asc(), id.asc()))
+        .select((mal_id, id))
+        .load::<(i32, i32)>(connection)?;
+    Ok(results)
 }
 
 // Function to load all anime_ids from the database
-pub fn load_all_anime_ids() -> Result<Vec<String>, DieselError> {
-    let mut connection = establish_connection();
+pub fn load_all_anime_ids(connection: &mut PgConnection) -> Result<Vec<String>, DieselError> {
     use crate::schema::anime_id::dsl::*;
-    let results = anime_id
-        .select(anime_name)
-        .load::<String>(&mut connection)?;
+    let results = anime_id.select(anime_name).load::<String>(connection)?;
     Ok(results)
 }
 
+// Function to load the IDs of every anime not fetched within `max_age`, oldest first.
+// Anime fetched before their fetch time was recorded count as stale
+pub fn load_stale_anime_ids(
+    max_age: TimeDelta,
+    connection: &mut PgConnection,
+) -> Result<Vec<i32>, DieselError> {
+    use crate::schema::anime::dsl::*;
+    anime
+        .filter(
+            last_fetched_at
+                .is_null()
+                .or(last_fetched_at.le(Utc::now() - max_age)),
+        )
+        .order((last_fetched_at.asc().nulls_first(), id.asc()))
+        .select(id)
+        .load::<i32>(connection)
+}
+
+// Function to load the names of every anime whose details were not fetched within
+// `max_age`, oldest first. Names never fetched count as stale
+pub fn load_stale_anime_names(
+    max_age: TimeDelta,
+    connection: &mut PgConnection,
+) -> Result<Vec<String>, DieselError> {
+    use crate::schema::anime_id::dsl::*;
+    anime_id
+        .filter(
+            last_fetched_at
+                .is_null()
+                .or(last_fetched_at.le(Utc::now() - max_age)),
+        )
+        .order((last_fetched_at.asc().nulls_first(), anime_name.asc()))
+        .select(anime_name)
+        .load::<String>(connection)
+}
+
+// Function to record that the details of an anime name were just fetched
+pub fn mark_anime_name_fetched(
+    name: &str,
+    connection: &mut PgConnection,
+) -> Result<(), DieselError> {
+    use crate::schema::anime_id::dsl::*;
+    diesel::update(anime_id.filter(anime_name.eq(name)))
+        .set(last_fetched_at.eq(now))
+        .execute(connection)?;
+    Ok(())
+}
+
 // Struct for deserializing anime data from API
+// Only the ID is stored, serde skips the remaining fields
 #[derive(Debug, Deserialize)]
 struct AnimeName {
     id: String,
-    name: String,
-    img: String,
-    episodes: Episodes,
-    duration: String,
-    rated: bool,
 }
 
-// Struct for deserializing episode data within AnimeName
-#[derive(Debug, Deserialize)]
-struct Episodes {
-    eps: Option<u32>,
-    sub: Option<u32>,
-    dub: Option<u32>,
-}
-
-// Function to asynchronously fetch anime data from an API
+// Function to asynchronously fetch anime data from an API, retrying transient failures
 // TODO: impl custom api and proxies
-pub async fn fetch_data(page_no: u16) -> Result<Vec<AnimeID>, CustomError> {
-    dotenv().ok();
-
-    let atoz_list_url = env::var("ATOZLIST_URL").expect("ATOZLIST_URL must be set");
-    let url = format!("{}{}", atoz_list_url, page_no);
-
-    let response = reqwest::get(&url).await?.error_for_status()?;
-    let anime_list: Vec<AnimeName> = response.json().await?;
+pub async fn fetch_data(config: &Config, page_no: u16) -> Result<Vec<AnimeID>, Error> {
+    let url = format!("{}{}", config.atoz_list_url, page_no);
+
+    let client = ClientFactory::global().api()?;
+    let anime_list: Vec<AnimeName> = RetryPolicy::from_config(config)
+        .run(|| async {
+            let response = http::get(&client, &url).await?;
+            http::read_json(&url, response).await
+        })
+        .await?;
 
     let anime_ids: Vec<AnimeID> = anime_list
         .iter()
@@ -193,62 +250,102 @@ pub async fn fetch_data(page_no: u16) -> Result<Vec<AnimeID>, CustomError> {
     Ok(anime_ids)
 }
 
-// Function to add new anime with corresponding anime IDs
-pub async fn add_new_anime_with_anime_id() -> Result<(), CustomError> {
-    let mut handles: Vec<JoinHandle<Result<(), CustomError>>> = vec![];
-    // TODO: use web scraping to find last page no
-    let NO_OF_PAGES: u16 = get_last_page_no_of_atoz_list().await?;
-    let mut count: u16 = 0;
-
-    while count < NO_OF_PAGES {
-        for i in 0..10 {
-            let handle = tokio::spawn(async move {
-                let page_number = count + i + 1;
-                if page_number <= NO_OF_PAGES {
-                    match fetch_data(page_number).await {
-                        Ok(anime_ids) => {
-                            for anime_id in anime_ids {
-                                insert_into_anime_id(&anime_id)?;
-                            }
-                        }
-                        Err(e) => (eprint!("{}", e)),
+// Function to add new anime with corresponding anime IDs, reporting into `run`
+pub async fn add_new_anime_with_anime_id(
+    config: &Config,
+    pool: &PgPool,
+    scheduler: &Scheduler,
+    run: &RunRecorder,
+) -> Result<(), Error> {
+    let no_of_pages: u16 = get_last_page_no_of_atoz_list(config).await?;
+    let config = config.clone();
+    let pool = pool.clone();
+    let job_run = run.clone();
+
+    let results = scheduler
+        .run((1..=no_of_pages).collect(), move |page_number| {
+            let config = config.clone();
+            let pool = pool.clone();
+            let run = job_run.clone();
+            async move {
+                match fetch_data(&config, page_number).await {
+                    Ok(anime_ids) => {
+                        let mut connection = pool.get()?;
+                        let inserted = insert_into_anime_ids(&anime_ids, &mut connection)?;
+                        // Names already stored are skipped by the insert
+                        let mut counts = RunCounts::default();
+                        counts.add_counts(
+                            ANIME_IDS_ENTITY,
+                            UpsertCounts {
+                                inserted,
+                                updated: 0,
+                                unchanged: anime_ids.len() - inserted,
+                            },
+                        );
+                        run.record(&counts);
+                    }
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        run.record_failure(
+                            ANIME_IDS_ENTITY,
+                            &format!("page {}", page_number),
+                            &e.to_string(),
+                        );
                     }
                 }
-                Ok(())
-            });
-            handles.push(handle);
-        }
-        count += 10;
-    }
+                Ok::<(), Error>(())
+            }
+        })
+        .await?;
 
-    // Wait for all tasks to complete and handle any errors
-    for handle in handles {
-        handle.await??;
-    }
+    // Report the first database error once every started page has finished
+    results.into_iter().collect::<Result<(), Error>>()?;
 
-    println!("{}", "Anime IDs fetching Complete.");
+    if scheduler.is_cancelled() {
+        println!("Anime IDs fetching cancelled.");
+    } else {
+        let counts = run.counts().get(ANIME_IDS_ENTITY);
+        println!(
+            "Anime IDs fetching Complete. {} new, {} known, {} pages failed.",
+            counts.upserts.inserted, counts.upserts.unchanged, counts.failed
+        );
+    }
 
     Ok(())
 }
 
 // Function to insert new anime ID into the anime_id table
-fn insert_into_anime_id(new_anime: &AnimeID) -> Result<(), DieselError> {
-    let mut connection = establish_connection();
+pub fn insert_into_anime_id(
+    new_anime: &AnimeID,
+    connection: &mut PgConnection,
+) -> Result<(), DieselError> {
     use crate::schema::anime_id::dsl::*;
 
-    // Check if the anime ID already exists
-    let anime_exists = diesel::select(diesel::dsl::exists(
-        anime_id.filter(anime_name.eq(&new_anime.anime_name)),
-    ))
-    .get_result(&mut connection)?;
-
-    if anime_exists {
-        return Ok(());
-    }
-
+    // Names are unique, already known ones are left untouched
     diesel::insert_into(anime_id)
         .values(new_anime)
-        .execute(&mut connection)?;
+        .on_conflict(anime_name)
+        .do_nothing()
+        .execute(connection)?;
 
     Ok(())
 }
+
+// Function to insert a page of anime IDs with a single statement
+pub fn insert_into_anime_ids(
+    new_anime_ids: &[AnimeID],
+    connection: &mut PgConnection,
+) -> Result<usize, DieselError> {
+    use crate::schema::anime_id::dsl::*;
+
+    if new_anime_ids.is_empty() {
+        return Ok(0);
+    }
+
+    // Names are unique, already known ones are left untouched
+    diesel::insert_into(anime_id)
+        .values(new_anime_ids)
+        .on_conflict(anime_name)
+        .do_nothing()
+        .execute(connection)
+}
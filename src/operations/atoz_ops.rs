@@ -1,61 +1,52 @@
 extern crate reqwest;
 extern crate scraper;
 
-use reqwest::Client;
-use scraper::error::SelectorErrorKind;
+use crate::config::Config;
+use crate::error::Error;
+use crate::http::{self, ClientFactory};
+use crate::retry::RetryPolicy;
 use scraper::{Html, Selector};
-use std::error::Error;
 
-#[derive(Debug)]
-enum CustomError {
-    ReqwestError(reqwest::Error),
-    ScraperError(SelectorErrorKind<'static>),
-}
+pub const ATOZ_LIST_PAGE_URL: &str = "https://hianime.to/az-list";
 
-//Implement conversions from specific error types to CustomError
-impl From<reqwest::Error> for CustomError {
-    fn from(err: reqwest::Error) -> Self {
-        CustomError::ReqwestError(err)
-    }
-}
+// Selector for the last page link
+const LAST_PAGE_SELECTOR: &str = "#main-wrapper > div > div.page-az-wrap > section > div.tab-content > div > div.pre-pagination.mt-5.mb-5 > nav > ul > li:last-child a";
 
-impl From<SelectorErrorKind<'static>> for CustomError {
-    fn from(err: SelectorErrorKind<'static>) -> Self {
-        CustomError::ScraperError(err)
-    }
-}
-
-// Function to get the data from the URL
-pub async fn get_curl_data() -> Result<String, Box<dyn Error>> {
-    let client = Client::new();
-    let response = client
-        .get("https://hianime.to/az-list")
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    Ok(response)
+// Function to get the data from the URL, retrying transient failures
+pub async fn get_curl_data(config: &Config) -> Result<String, Error> {
+    let client = ClientFactory::global().direct()?;
+    RetryPolicy::from_config(config)
+        .run(|| async {
+            let response = http::get(&client, ATOZ_LIST_PAGE_URL).await?;
+            http::read_text(ATOZ_LIST_PAGE_URL, response).await
+        })
+        .await
 }
 
 // Function to extract the last page number from the response
-pub async fn get_last_page_no_of_atoz_list() -> Result<u16, Box<dyn Error>> {
-    let response = get_curl_data().await?;
+pub async fn get_last_page_no_of_atoz_list(config: &Config) -> Result<u16, Error> {
+    let response = get_curl_data(config).await?;
     let document = Html::parse_document(&response);
 
-    // Selector for the last page link
-    let nav_selector = Selector::parse("#main-wrapper > div > div.page-az-wrap > section > div.tab-content > div > div.pre-pagination.mt-5.mb-5 > nav > ul > li:last-child a")?;
+    let selector_error = |message: String| Error::Selector {
+        url: ATOZ_LIST_PAGE_URL.to_string(),
+        selector: LAST_PAGE_SELECTOR.to_string(),
+        message,
+    };
+
+    let nav_selector =
+        Selector::parse(LAST_PAGE_SELECTOR).map_err(|err| selector_error(err.to_string()))?;
 
     // Find the last page link
-    if let Some(last_page_element) = document.select(&nav_selector).last() {
-        if let Some(href) = last_page_element.value().attr("href") {
-            if let Some(page_str) = href.split('=').last() {
-                if let Ok(page_number) = page_str.parse::<u16>() {
-                    return Ok(page_number);
-                }
-            }
-        }
-    }
-
-    Ok(212)
+    let href = document
+        .select(&nav_selector)
+        .next_back()
+        .and_then(|last_page_element| last_page_element.value().attr("href"))
+        .ok_or_else(|| selector_error(String::from("no last page link found")))?;
+
+    let page_str = href.split('=').next_back().unwrap_or_default();
+    page_str.parse::<u16>().map_err(|err| Error::Parse {
+        url: ATOZ_LIST_PAGE_URL.to_string(),
+        message: format!("invalid last page number `{}`: {}", page_str, err),
+    })
 }
@@ -0,0 +1,234 @@
+// character_ops.rs
+
+use crate::config::Config;
+use crate::db::PgPool;
+use crate::error::Error;
+use crate::http::{self, ClientFactory};
+use crate::model::{AnimeCharacter, Character, VoiceActor};
+use crate::operations::history_ops::{inserted_row, upsert_outcome, UpsertOutcome};
+use crate::operations::jikan_ops::sync_mal_ids;
+use crate::operations::staff_ops::{insert_staff_if_missing, Images, Person};
+use crate::operations::sync_run_ops::{
+    RunCounts, RunRecorder, ANIME_CHARACTERS_ENTITY, CHARACTERS_ENTITY, VOICE_ACTORS_ENTITY,
+};
+use crate::operations::sync_state_ops::CHARACTERS_PIPELINE;
+use crate::retry::RetryPolicy;
+use crate::scheduler::Scheduler;
+use crate::schema::{anime_characters, characters, voice_actors};
+use chrono::TimeDelta;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::query_dsl::methods::FilterDsl;
+use diesel::result::Error as DieselError;
+use diesel::upsert::excluded;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct CharactersResponse {
+    pub data: Vec<CharacterData>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CharacterData {
+    character: CharacterInfo,
+    role: String,
+    voice_actors: Vec<VoiceActorData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CharacterInfo {
+    mal_id: i32,
+    url: String,
+    images: Images,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct VoiceActorData {
+    person: Person,
+    language: String,
+}
+
+impl CharacterData {
+    pub fn to_character(&self) -> Character {
+        Character {
+            mal_id: self.character.mal_id,
+            name: self.character.name.clone(),
+            mal_url: self.character.url.clone(),
+            image: self.character.images.jpg_url().to_string(),
+        }
+    }
+
+    pub fn to_anime_character(&self, anime_table_id: i32) -> AnimeCharacter {
+        AnimeCharacter {
+            anime_id: anime_table_id,
+            character_id: self.character.mal_id,
+            role: self.role.clone(),
+        }
+    }
+
+    pub fn to_voice_actors(&self, anime_table_id: i32) -> Vec<VoiceActor> {
+        self.voice_actors
+            .iter()
+            .map(|voice_actor| VoiceActor {
+                anime_id: anime_table_id,
+                character_id: self.character.mal_id,
+                staff_id: voice_actor.person.mal_id,
+                language: voice_actor.language.clone(),
+            })
+            .collect()
+    }
+}
+
+// Insert a character, or refresh it when it exists
+pub fn upsert_character(
+    new_character: &Character,
+    connection: &mut PgConnection,
+) -> Result<UpsertOutcome, DieselError> {
+    let inserted = diesel::insert_into(characters::table)
+        .values(new_character)
+        .on_conflict(characters::mal_id)
+        .do_update()
+        .set((
+            characters::name.eq(excluded(characters::name)),
+            characters::mal_url.eq(excluded(characters::mal_url)),
+            characters::image.eq(excluded(characters::image)),
+        ))
+        // Leave the row alone when it already holds every value
+        .filter(
+            characters::name
+                .is_distinct_from(excluded(characters::name))
+                .or(characters::mal_url.is_distinct_from(excluded(characters::mal_url)))
+                .or(characters::image.is_distinct_from(excluded(characters::image))),
+        )
+        .returning(inserted_row())
+        .get_result::<bool>(connection)
+        .optional()?;
+
+    Ok(upsert_outcome(inserted))
+}
+
+// Insert an anime_characters link, or update the role when it exists
+pub fn upsert_anime_character(
+    new_anime_character: &AnimeCharacter,
+    connection: &mut PgConnection,
+) -> Result<UpsertOutcome, DieselError> {
+    let inserted = diesel::insert_into(anime_characters::table)
+        .values(new_anime_character)
+        .on_conflict((anime_characters::anime_id, anime_characters::character_id))
+        .do_update()
+        .set(anime_characters::role.eq(excluded(anime_characters::role)))
+        .filter(anime_characters::role.is_distinct_from(excluded(anime_characters::role)))
+        .returning(inserted_row())
+        .get_result::<bool>(connection)
+        .optional()?;
+
+    Ok(upsert_outcome(inserted))
+}
+
+// Insert a voice_actors link, or update the language when it exists
+pub fn upsert_voice_actor(
+    new_voice_actor: &VoiceActor,
+    connection: &mut PgConnection,
+) -> Result<UpsertOutcome, DieselError> {
+    let inserted = diesel::insert_into(voice_actors::table)
+        .values(new_voice_actor)
+        .on_conflict((
+            voice_actors::anime_id,
+            voice_actors::character_id,
+            voice_actors::staff_id,
+        ))
+        .do_update()
+        .set(voice_actors::language.eq(excluded(voice_actors::language)))
+        .filter(voice_actors::language.is_distinct_from(excluded(voice_actors::language)))
+        .returning(inserted_row())
+        .get_result::<bool>(connection)
+        .optional()?;
+
+    Ok(upsert_outcome(inserted))
+}
+
+// Store the characters of one MAL ID with their voice actors and link them to every
+// anime sharing that MAL ID
+pub fn save_characters_response(
+    response: &CharactersResponse,
+    anime_ids: &[i32],
+    connection: &mut PgConnection,
+) -> Result<RunCounts, DieselError> {
+    connection.transaction(|connection| {
+        let mut counts = RunCounts::default();
+        for character in &response.data {
+            counts.add(
+                CHARACTERS_ENTITY,
+                upsert_character(&character.to_character(), connection)?,
+            );
+            // Voice actors are staff, rows already synced from the staff endpoint are kept
+            for voice_actor in &character.voice_actors {
+                insert_staff_if_missing(&voice_actor.person.to_staff(vec![]), connection)?;
+            }
+
+            for anime_id in anime_ids {
+                counts.add(
+                    ANIME_CHARACTERS_ENTITY,
+                    upsert_anime_character(&character.to_anime_character(*anime_id), connection)?,
+                );
+                for voice_actor in character.to_voice_actors(*anime_id) {
+                    counts.add(
+                        VOICE_ACTORS_ENTITY,
+                        upsert_voice_actor(&voice_actor, connection)?,
+                    );
+                }
+            }
+        }
+        Ok(counts)
+    })
+}
+
+pub async fn fetch_jikan_characters_response(
+    config: &Config,
+    anime_mal_id: i32,
+) -> Result<CharactersResponse, Error> {
+    let client = ClientFactory::global().api()?;
+    let characters_url = format!("{}/anime/{}/characters", config.jikan_api_url, anime_mal_id);
+    RetryPolicy::from_config(config)
+        .run(|| async {
+            let response = http::get(&client, &characters_url).await?;
+            http::read_json(&characters_url, response).await
+        })
+        .await
+}
+
+// Fetch and store the characters and voice actors of every anime with a MAL ID. With
+// `max_age`, MAL IDs whose characters were synced more recently than that are skipped
+pub async fn store_character_data(
+    config: &Config,
+    pool: &PgPool,
+    max_age: Option<TimeDelta>,
+    scheduler: &Scheduler,
+    run: &RunRecorder,
+) -> Result<(), Error> {
+    let config = config.clone();
+    let job_pool = pool.clone();
+
+    sync_mal_ids(
+        CHARACTERS_PIPELINE,
+        pool,
+        max_age,
+        scheduler,
+        run,
+        move |mal_id, anime_ids| {
+            let config = config.clone();
+            let pool = job_pool.clone();
+            async move {
+                let response = fetch_jikan_characters_response(&config, mal_id).await?;
+                let mut connection = pool.get()?;
+                Ok(save_characters_response(
+                    &response,
+                    &anime_ids,
+                    &mut connection,
+                )?)
+            }
+        },
+    )
+    .await
+}
@@ -1,72 +1,43 @@
-use crate::db::establish_connection;
+use crate::anime_fields::{
+    parse_aired, parse_duration_minutes, parse_mal_score, parse_premiered, AgeRating, AiringStatus,
+    AudioAvailability, Category,
+};
+use crate::config::Config;
+use crate::db::PgPool;
+use crate::error::Error;
+use crate::http::{self, ClientFactory};
 use crate::model::{Anime, Episode};
-use crate::operations::anime_ops::{add_new_anime, load_all_anime_ids};
+use crate::operations::anime_ops::{
+    load_all_anime_ids, load_stale_anime_names, mark_anime_name_fetched,
+};
+use crate::operations::history_ops::{
+    episode_changes, record_episode_changes, UpsertCounts, UpsertOutcome,
+};
+use crate::operations::record_ops::{write_anime_record, AnimeRecord};
+use crate::operations::sync_run_ops::{RunRecorder, ANIME_ENTITY};
+use crate::operations::sync_state_ops::{
+    count_sync_items, load_sync_items, mark_sync_done, mark_sync_failed, queue_sync_items,
+    requeue_sync_items, reset_sync_items, SyncMode, SyncStatus, DETAILS_PIPELINE,
+};
+use crate::proxy::{load_proxy_pool, site_answered, ProxyPool};
+use crate::retry::RetryPolicy;
+use crate::scheduler::Scheduler;
+use diesel::dsl::now;
+use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use diesel::result::Error as DieselError;
-use dotenvy::dotenv;
-use rand::seq::SliceRandom;
-use reqwest::Client;
+use diesel::upsert::excluded;
 use serde::Deserialize;
-use std::env;
-use tokio::task::JoinHandle;
-use tokio::time::Duration;
+use std::collections::{HashMap, HashSet};
+use tokio::time::{Duration, Instant};
 
-use super::anime_ops::CustomError;
+// Episodes bind 5 parameters each, Postgres allows 65535 per statement
+const EPISODE_BATCH_SIZE: usize = 10_000;
+// Slow proxies are given up on early, another one is tried instead
+const ANIME_DETAILS_TIMEOUT: Duration = Duration::from_secs(5);
+// Proxies tried one after another, without waiting, in one attempt to fetch anime details
+const PROXIES_PER_ATTEMPT: u32 = 5;
 
-// Define a struct to hold proxy data
-#[derive(Debug, Clone)]
-pub struct Proxy {
-    pub address: String,
-}
-
-// Function to get a random proxy from the list
-pub fn get_random_proxy(proxies: &[Proxy]) -> Option<Proxy> {
-    proxies.choose(&mut rand::thread_rng()).cloned()
-}
-
-// Fetch proxy list from URL
-pub async fn fetch_proxy_list(url: &str) -> Result<Vec<Proxy>, CustomError> {
-    let client = Client::new();
-    let response = client.get(url).send().await?.text().await?;
-    let proxies = response
-        .lines()
-        .filter_map(|line| {
-            let line = line.trim();
-            if !line.is_empty() {
-                Some(Proxy {
-                    address: line.to_string(),
-                })
-            } else {
-                None
-            }
-        })
-        .collect();
-    Ok(proxies)
-}
-
-// Load proxies from multiple sources
-pub async fn load_proxies() -> Result<Vec<Proxy>, CustomError> {
-    dotenv().ok();
-
-    let sock5_url = env::var("SOCK5_URL").expect("SOCK5_URL must be set");
-    let sock4_url = env::var("SOCK4_URL").expect("SOCK4_URL must be set");
-    let http_url = env::var("HTTP_URL").expect("HTTP_URL must be set");
-
-    let (sock5_proxies, sock4_proxies, http_proxies) = tokio::try_join!(
-        fetch_proxy_list(&sock5_url),
-        fetch_proxy_list(&sock4_url),
-        fetch_proxy_list(&http_url)
-    )?;
-
-    let mut all_proxies = Vec::new();
-    all_proxies.extend(sock5_proxies);
-    all_proxies.extend(sock4_proxies);
-    all_proxies.extend(http_proxies);
-
-    Ok(all_proxies)
-}
-
-// Struct for deserializing anime data from API
 #[derive(Debug, Deserialize)]
 pub struct AnimeDetails {
     pub id: i32,
@@ -122,7 +93,7 @@ impl Default for AnimeDetails {
             premiered: Some(String::from("")),
             aired: Some(String::from("")),
             status: Some(String::from("")),
-            mal_score: Some(String::from("n/a")),
+            mal_score: Some(String::from("")),
             studios: Some(String::from("")),
             producers: Some(String::from("")),
             genres: Some(String::from("")),
@@ -135,6 +106,74 @@ impl Default for AnimeDetails {
     }
 }
 
+impl AnimeDetails {
+    // Convert the fetched details into the rows stored for the anime
+    pub fn into_record(self) -> AnimeRecord {
+        // Missing values are fetched as empty strings
+        let raw = |value: Option<String>| value.filter(|value| !value.trim().is_empty());
+        let duration = raw(self.duration);
+        let premiered = raw(self.premiered);
+        let aired = raw(self.aired);
+        let (premiered_season, premiered_year) =
+            parse_premiered(premiered.as_deref().unwrap_or_default());
+        let (aired_start, aired_end) = parse_aired(aired.as_deref().unwrap_or_default());
+        let anime = Anime {
+            id: self.id,
+            title: self.title.unwrap_or(String::from("Unknown Title")),
+            description: self
+                .description
+                .unwrap_or(String::from("No description available")),
+            mal_id: self.mal_id.unwrap_or_default(),
+            al_id: self.al_id.unwrap_or_default(),
+            japanese_title: Some(self.japanese_title.unwrap_or_default()),
+            synonyms: Some(self.synonyms.unwrap_or_default()),
+            image: self.image.unwrap_or_default(),
+            category: self.category.as_deref().and_then(Category::parse),
+            rating: self.rating.as_deref().and_then(AgeRating::parse),
+            quality: self.quality.unwrap_or_default(),
+            status: self.status.as_deref().and_then(AiringStatus::parse),
+            mal_score: self.mal_score.as_deref().and_then(parse_mal_score),
+            studios: self.studios.unwrap_or_default(),
+            producers: self.producers.unwrap_or_default(),
+            genres: self.genres.unwrap_or_default(),
+            sub_episodes: self.sub_episodes.unwrap_or_default(),
+            dub_episodes: self.dub_episodes.unwrap_or_default(),
+            total_episodes: self.total_episodes.unwrap_or_default(),
+            sub_or_dub: self
+                .sub_or_dub
+                .as_deref()
+                .and_then(AudioAvailability::parse),
+            duration_minutes: duration.as_deref().and_then(parse_duration_minutes),
+            duration,
+            premiered,
+            aired,
+            premiered_season,
+            premiered_year,
+            aired_start,
+            aired_end,
+        };
+        let episodes = self
+            .episodes
+            .unwrap_or_default()
+            .into_iter()
+            .map(|episode_data| Episode {
+                id: episode_data.id.unwrap_or_default(),
+                title: episode_data.title.unwrap_or_default(),
+                is_filler: episode_data.is_filler.unwrap_or_default(),
+                episode_no: episode_data.episode_no.unwrap_or_default(),
+                anime_id: self.id,
+            })
+            .collect();
+
+        AnimeRecord {
+            anime,
+            episodes,
+            staff: vec![],
+            anime_staff: vec![],
+        }
+    }
+}
+
 impl Default for EpisodeDetails {
     fn default() -> Self {
         EpisodeDetails {
@@ -146,162 +185,281 @@ impl Default for EpisodeDetails {
     }
 }
 
-// Add new episode to the database
-pub fn add_new_episode(new_episode: Episode) -> Result<(), DieselError> {
-    let mut connection = establish_connection();
+// Add new episode to the database, recording every field an update changes
+pub fn add_new_episode(
+    new_episode: Episode,
+    sync_run_id: Option<i64>,
+    connection: &mut PgConnection,
+) -> Result<(), DieselError> {
+    add_new_episodes(std::slice::from_ref(&new_episode), sync_run_id, connection)?;
+    Ok(())
+}
+
+// Upsert a batch of episodes, one statement per chunk. Episodes whose stored row already
+// holds every value are skipped, the changed fields of the others are recorded
+pub fn add_new_episodes(
+    new_episodes: &[Episode],
+    sync_run_id: Option<i64>,
+    connection: &mut PgConnection,
+) -> Result<UpsertCounts, DieselError> {
     use crate::schema::episodes::dsl::*;
 
-    // Check if the episode already exists
-    let episode_exists =
-        diesel::select(diesel::dsl::exists(episodes.filter(id.eq(&new_episode.id))))
-            .get_result(&mut connection)?;
+    // A single upsert can't touch the same row twice, keep the last copy of each ID
+    let mut seen = HashSet::new();
+    let mut unique_episodes: Vec<&Episode> = new_episodes
+        .iter()
+        .rev()
+        .filter(|episode| seen.insert(episode.id.as_str()))
+        .collect();
+    unique_episodes.reverse();
+
+    let mut counts = UpsertCounts::default();
+    for chunk in unique_episodes.chunks(EPISODE_BATCH_SIZE) {
+        let chunk_ids: Vec<&str> = chunk.iter().map(|episode| episode.id.as_str()).collect();
+        let stored: HashMap<String, Episode> = episodes
+            .filter(id.eq_any(&chunk_ids))
+            .select(Episode::as_select())
+            .for_update()
+            .load::<Episode>(connection)?
+            .into_iter()
+            .map(|episode| (episode.id.clone(), episode))
+            .collect();
+
+        let mut changed_episodes = Vec::new();
+        let mut unchanged_ids = Vec::new();
+        for new_episode in chunk {
+            let Some(stored_episode) = stored.get(&new_episode.id) else {
+                counts.add(UpsertOutcome::Inserted);
+                changed_episodes.push(*new_episode);
+                continue;
+            };
+            let changes = episode_changes(stored_episode, new_episode);
+            if changes.is_empty() {
+                counts.add(UpsertOutcome::Unchanged);
+                unchanged_ids.push(new_episode.id.as_str());
+                continue;
+            }
+            record_episode_changes(new_episode, sync_run_id, &changes, connection)?;
+            counts.add(UpsertOutcome::Updated);
+            changed_episodes.push(*new_episode);
+        }
+        if !unchanged_ids.is_empty() {
+            diesel::update(episodes.filter(id.eq_any(&unchanged_ids)))
+                .set(last_fetched_at.eq(now))
+                .execute(connection)?;
+        }
+        if changed_episodes.is_empty() {
+            continue;
+        }
 
-    if episode_exists {
-        // Update existing episode
-        diesel::update(episodes.filter(id.eq(&new_episode.id)))
+        diesel::insert_into(episodes)
+            .values(changed_episodes)
+            .on_conflict(id)
+            .do_update()
             .set((
-                title.eq(&new_episode.title),
-                is_filler.eq(new_episode.is_filler),
-                episode_no.eq(new_episode.episode_no),
-                anime_id.eq(new_episode.anime_id),
+                title.eq(excluded(title)),
+                is_filler.eq(excluded(is_filler)),
+                episode_no.eq(excluded(episode_no)),
+                anime_id.eq(excluded(anime_id)),
+                updated_at.eq(now),
+                last_fetched_at.eq(now),
             ))
-            .execute(&mut connection)?;
-    } else {
-        // Insert new episode
-        diesel::insert_into(episodes)
-            .values(&new_episode)
-            .execute(&mut connection)?;
+            .execute(connection)?;
     }
 
-    Ok(())
+    Ok(counts)
 }
 
-// Function to asynchronously fetch anime data from an API
+// Function to asynchronously fetch anime data from an API. Transient failures are retried,
+// each attempt trying proxies until one of them gets an answer from the site
 pub async fn fetch_anime_details(
+    config: &Config,
     anime_id: String,
-    proxies: &[Proxy],
-) -> Result<AnimeDetails, CustomError> {
-    let mut attempts = 0;
-    let max_attempts = 5;
-    dotenv().ok();
-    let anime_fetcher_url = env::var("ANIME_FETCHER_URL").expect("ANIME_FETCHER_URL must be set.");
-
-    while attempts < max_attempts {
-        if let Some(proxy) = get_random_proxy(proxies) {
-            let client = Client::builder()
-                .proxy(reqwest::Proxy::http(&proxy.address)?)
-                .timeout(Duration::from_secs(5))
-                .build()?;
-
-            let url = format!("{}/{}", anime_fetcher_url, anime_id);
-            let response = client.get(&url).send().await;
+    proxies: Option<&ProxyPool>,
+) -> Result<AnimeDetails, Error> {
+    let url = format!("{}/{}", config.anime_fetcher_url, anime_id);
+    RetryPolicy::from_config(config)
+        .run(|| fetch_anime_details_once(&url, proxies))
+        .await
+        .map_err(|e| e.for_anime(anime_id))
+}
 
-            match response {
-                Ok(resp) => {
-                    if resp.status().is_success() {
-                        let anime_data = resp.json().await?;
-                        return Ok(anime_data);
-                    } else {
-                        eprintln!("Failed with status: {:?}", resp.status());
-                    }
+// Fetch anime details once, directly without a pool. With a pool, a proxy that fails or
+// sends back a body that doesn't parse is reported and another one is tried at once, up to
+// PROXIES_PER_ATTEMPT of them. Only a parsed body or an answer of the site, such as a 404,
+// counts as a success of the proxy
+async fn fetch_anime_details_once(
+    url: &str,
+    proxies: Option<&ProxyPool>,
+) -> Result<AnimeDetails, Error> {
+    let Some(proxies) = proxies else {
+        let client = ClientFactory::global().direct()?;
+        let response = http::get_with_timeout(&client, url, ANIME_DETAILS_TIMEOUT).await?;
+        return http::read_json(url, response).await;
+    };
+
+    let mut tries = 0;
+    loop {
+        let proxy = proxies.choose().ok_or(Error::NoProxiesAvailable)?;
+        let client = ClientFactory::global().proxied(&proxy)?;
+        let started = Instant::now();
+        let error = match http::get_with_timeout(&client, url, ANIME_DETAILS_TIMEOUT).await {
+            Ok(response) => match http::read_json(url, response).await {
+                Ok(details) => {
+                    proxies.report_success(&proxy, started.elapsed());
+                    return Ok(details);
                 }
-                Err(e) => eprintln!("Failed to fetch with proxy: {:?}. Error: {:?}", proxy, e),
+                Err(e) => e,
+            },
+            // Another proxy would get the same answer, retrying is up to the retry policy
+            Err(e) if site_answered(&e) => {
+                proxies.report_success(&proxy, started.elapsed());
+                return Err(e);
             }
-        } else {
-            return Err(CustomError::NoProxiesAvailable);
+            Err(e) => e,
+        };
+
+        eprintln!("Failed to fetch with proxy {}. Error: {}", proxy, error);
+        proxies.report_failure(&proxy);
+        tries += 1;
+        if tries == PROXIES_PER_ATTEMPT {
+            return Err(error);
         }
-
-        attempts += 1;
-        // tokio::time::sleep(Duration::from_secs(1)).await;
     }
-
-    Err(CustomError::FailedToFetchAfterRetries)
 }
 
-// Store anime and episode data
-pub async fn store_anime_and_episode_data() -> Result<(), CustomError> {
-    let anime_list = load_all_anime_ids().map_err(|e| CustomError::from(e))?;
-    let proxies = load_proxies().await?;
-
-    let mut handles: Vec<JoinHandle<Result<(), CustomError>>> = vec![];
-    let no_of_animes: usize = anime_list.len();
-    let chunk_size: usize = 100;
-
-    let mut count: usize = 0;
-    while count < no_of_animes {
-        let end = (count + chunk_size).min(no_of_animes);
-        let chunk: Vec<_> = anime_list[count..end].to_vec();
-
-        let proxies = proxies.clone();
-
-        let handle = tokio::spawn(async move {
-            for anime in chunk {
-                match fetch_anime_details(anime, &proxies).await {
-                    Ok(mut anime_data) => {
-                        anime_data.title = anime_data.title.or(Some(String::from("Unknown Title")));
-                        anime_data.description = anime_data
-                            .description
-                            .or(Some(String::from("No description available")));
+// Fetch one anime and store it with its episodes, reporting what was written into `run`.
+// Returns the ID of the anime
+async fn fetch_and_store_anime(
+    config: &Config,
+    anime: &str,
+    proxies: Option<&ProxyPool>,
+    pool: &PgPool,
+    run: &RunRecorder,
+) -> Result<i32, Error> {
+    let anime_data = fetch_anime_details(config, anime.to_string(), proxies).await?;
+    let record = anime_data.into_record();
+    let anime_id = record.anime.id;
+    let for_anime = |e: Error| e.for_anime(anime);
+
+    let mut connection = pool.get().map_err(|e| for_anime(e.into()))?;
+    run.record(
+        &write_anime_record(record, Some(run.id()), &mut connection)
+            .map_err(|e| for_anime(e.into()))?,
+    );
+    mark_anime_name_fetched(anime, &mut connection).map_err(|e| for_anime(e.into()))?;
+
+    Ok(anime_id)
+}
 
-                        let anime_detail = Anime {
-                            id: anime_data.id,
-                            title: anime_data.title.unwrap_or_default(),
-                            description: anime_data.description.unwrap_or_default(),
-                            mal_id: anime_data.mal_id.unwrap_or_default(),
-                            al_id: anime_data.al_id.unwrap_or_default(),
-                            japanese_title: Some(anime_data.japanese_title.unwrap_or_default()),
-                            synonyms: Some(anime_data.synonyms.unwrap_or_default()),
-                            image: anime_data.image.unwrap_or_default(),
-                            category: anime_data.category.unwrap_or_default(),
-                            rating: anime_data.rating.unwrap_or_default(),
-                            quality: anime_data.quality.unwrap_or_default(),
-                            duration: anime_data.duration.unwrap_or_default(),
-                            premiered: anime_data.premiered.unwrap_or_default(),
-                            aired: anime_data.aired.unwrap_or_default(),
-                            status: anime_data.status.unwrap_or_default(),
-                            mal_score: anime_data.mal_score.unwrap_or_default(),
-                            studios: anime_data.studios.unwrap_or_default(),
-                            producers: anime_data.producers.unwrap_or_default(),
-                            genres: anime_data.genres.unwrap_or_default(),
-                            sub_episodes: anime_data.sub_episodes.unwrap_or_default(),
-                            dub_episodes: anime_data.dub_episodes.unwrap_or_default(),
-                            total_episodes: anime_data.total_episodes.unwrap_or_default(),
-                            sub_or_dub: anime_data.sub_or_dub.unwrap_or_default(),
-                        };
-                        add_new_anime(anime_detail)?;
-                        println!("{}", anime_data.id);
+// Store anime and episode data, resuming from the checkpoints of earlier runs
+pub async fn store_anime_and_episode_data(
+    config: &Config,
+    pool: &PgPool,
+    mode: SyncMode,
+    scheduler: &Scheduler,
+    run: &RunRecorder,
+) -> Result<(), Error> {
+    let anime_list = {
+        let mut connection = pool.get()?;
+        if mode != SyncMode::RetryFailed {
+            let anime_names = load_all_anime_ids(&mut connection)?;
+            queue_sync_items(DETAILS_PIPELINE, &anime_names, &mut connection)?;
+        }
+        match mode {
+            SyncMode::Restart => {
+                reset_sync_items(DETAILS_PIPELINE, &mut connection)?;
+            }
+            SyncMode::Stale(max_age) => {
+                let stale_names = load_stale_anime_names(max_age, &mut connection)?;
+                requeue_sync_items(DETAILS_PIPELINE, &stale_names, &mut connection)?;
+            }
+            SyncMode::Resume | SyncMode::RetryFailed => {}
+        }
 
-                        if let Some(episodes) = anime_data.episodes {
-                            for episode_data in episodes {
-                                let episode_detail = Episode {
-                                    id: episode_data.id.unwrap_or_default(),
-                                    title: episode_data.title.unwrap_or_default(),
-                                    is_filler: episode_data.is_filler.unwrap_or_default(),
-                                    episode_no: episode_data.episode_no.unwrap_or_default(),
-                                    anime_id: anime_data.id,
-                                };
-                                add_new_episode(episode_detail)?;
-                            }
-                        }
+        let status = match mode {
+            SyncMode::RetryFailed => SyncStatus::Failed,
+            SyncMode::Resume | SyncMode::Restart | SyncMode::Stale(_) => SyncStatus::Pending,
+        };
+        load_sync_items(DETAILS_PIPELINE, status, &mut connection)?
+    };
+    println!(
+        "{} anime to fetch in sync run {}.",
+        anime_list.len(),
+        run.id()
+    );
+
+    let proxies = load_proxy_pool(config).await?;
+    let _revalidation = proxies.as_ref().map(|proxies| proxies.spawn_revalidation());
+    let config = config.clone();
+    let job_proxies = proxies.clone();
+    let job_pool = pool.clone();
+    let job_run = run.clone();
+    let job_scheduler = scheduler.clone();
+
+    let results = scheduler
+        .run(anime_list, move |anime| {
+            let config = config.clone();
+            let proxies = job_proxies.clone();
+            let pool = job_pool.clone();
+            let run = job_run.clone();
+            let scheduler = job_scheduler.clone();
+            async move {
+                // Stop once too few proxies are left, the anime stays pending
+                if let Some(proxies) = &proxies {
+                    if proxies.ensure_healthy().is_err() {
+                        scheduler.cancel();
+                        return Ok(());
                     }
-                    Err(e) => eprintln!("Failed to fetch anime details: {:?}", e),
                 }
+                let result =
+                    fetch_and_store_anime(&config, &anime, proxies.as_deref(), &pool, &run).await;
+
+                // Checkpoint the outcome so a restarted run skips this anime
+                let mut connection = pool.get()?;
+                match result {
+                    Ok(anime_id) => {
+                        mark_sync_done(DETAILS_PIPELINE, &anime, &mut connection)?;
+                        println!("{}", anime_id);
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to sync {}", e);
+                        run.record_failure(ANIME_ENTITY, &anime, &e.to_string());
+                        mark_sync_failed(
+                            DETAILS_PIPELINE,
+                            &anime,
+                            &e.to_string(),
+                            &mut connection,
+                        )?;
+                    }
+                }
+                Ok::<(), Error>(())
             }
-            Ok(())
-        });
+        })
+        .await?;
+
+    // Errors here come from the checkpoint itself, the anime stays pending
+    for result in results {
+        if let Err(e) = result {
+            eprintln!("Task failed: {}", e);
+        }
+    }
 
-        handles.push(handle);
-        count += chunk_size;
+    if let Some(proxies) = &proxies {
+        proxies.ensure_healthy()?;
     }
 
-    // Wait for all tasks to complete and handle any errors
-    for handle in handles {
-        if let Err(e) = handle.await? {
-            eprintln!("Task failed: {:?}", e);
-        }
+    if scheduler.is_cancelled() {
+        println!("Anime and Episode Data fetching cancelled, the next run resumes from here.");
     }
 
-    println!("{}", "Anime and Episode Data fetching Complete.");
+    let mut connection = pool.get()?;
+    println!(
+        "Anime and Episode Data fetching Complete. {} done, {} failed, {} pending.",
+        count_sync_items(DETAILS_PIPELINE, SyncStatus::Done, &mut connection)?,
+        count_sync_items(DETAILS_PIPELINE, SyncStatus::Failed, &mut connection)?,
+        count_sync_items(DETAILS_PIPELINE, SyncStatus::Pending, &mut connection)?,
+    );
 
     Ok(())
 }
@@ -0,0 +1,257 @@
+// history_ops.rs
+
+use crate::anime_fields::{AgeRating, AiringStatus, AudioAvailability, Category, Season};
+use crate::model::{Anime, AnimeHistory, Episode, EpisodeHistory};
+use crate::schema::{anime_history, episode_history};
+use chrono::NaiveDate;
+use diesel::dsl::sql;
+use diesel::expression::SqlLiteral;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::Bool;
+
+// What an upsert did to a row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpsertOutcome {
+    Inserted,
+    Updated,
+    // The stored row already held every value, nothing was written
+    Unchanged,
+}
+
+// Outcomes of an upsert of many rows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UpsertCounts {
+    pub inserted: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+}
+
+impl UpsertCounts {
+    pub fn add(&mut self, outcome: UpsertOutcome) {
+        match outcome {
+            UpsertOutcome::Inserted => self.inserted += 1,
+            UpsertOutcome::Updated => self.updated += 1,
+            UpsertOutcome::Unchanged => self.unchanged += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: UpsertCounts) {
+        self.inserted += other.inserted;
+        self.updated += other.updated;
+        self.unchanged += other.unchanged;
+    }
+
+    // Rows the upsert was given, whatever it did with them
+    pub fn total(&self) -> usize {
+        self.inserted + self.updated + self.unchanged
+    }
+}
+
+// Outcome of an upsert returning `inserted_row()`, `None` when its filter skipped the update
+pub fn upsert_outcome(inserted: Option<bool>) -> UpsertOutcome {
+    match inserted {
+        Some(true) => UpsertOutcome::Inserted,
+        Some(false) => UpsertOutcome::Updated,
+        None => UpsertOutcome::Unchanged,
+    }
+}
+
+// Expression to return from an upsert telling an inserted row from an updated one,
+// Postgres leaves `xmax` at 0 only for rows the statement inserted
+pub fn inserted_row() -> SqlLiteral<Bool> {
+    sql::<Bool>("xmax = 0")
+}
+
+// Text stored in the history for a value, `None` when it is missing
+pub trait HistoryValue {
+    fn history_value(&self) -> Option<String>;
+}
+
+macro_rules! display_history_value {
+    ($($ty:ty),+) => {
+        $(impl HistoryValue for $ty {
+            fn history_value(&self) -> Option<String> {
+                Some(self.to_string())
+            }
+        })+
+    };
+}
+
+display_history_value!(
+    String,
+    i32,
+    f32,
+    bool,
+    NaiveDate,
+    Category,
+    AgeRating,
+    AiringStatus,
+    AudioAvailability,
+    Season
+);
+
+impl<T: HistoryValue> HistoryValue for Option<T> {
+    fn history_value(&self) -> Option<String> {
+        self.as_ref().and_then(HistoryValue::history_value)
+    }
+}
+
+// A field whose stored value differs from the fetched one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+// Collects the changed fields of a row
+#[derive(Default)]
+struct Diff {
+    changes: Vec<FieldChange>,
+}
+
+impl Diff {
+    fn field<T: PartialEq + HistoryValue>(&mut self, field: &'static str, old: &T, new: &T) {
+        if old != new {
+            self.changes.push(FieldChange {
+                field,
+                old_value: old.history_value(),
+                new_value: new.history_value(),
+            });
+        }
+    }
+}
+
+// Function to list the fields of a stored anime an upsert of `new` would change
+pub fn anime_changes(old: &Anime, new: &Anime) -> Vec<FieldChange> {
+    let mut diff = Diff::default();
+    diff.field("title", &old.title, &new.title);
+    diff.field("description", &old.description, &new.description);
+    diff.field("mal_id", &old.mal_id, &new.mal_id);
+    diff.field("al_id", &old.al_id, &new.al_id);
+    diff.field("japanese_title", &old.japanese_title, &new.japanese_title);
+    diff.field("synonyms", &old.synonyms, &new.synonyms);
+    diff.field("image", &old.image, &new.image);
+    diff.field("category", &old.category, &new.category);
+    diff.field("rating", &old.rating, &new.rating);
+    diff.field("quality", &old.quality, &new.quality);
+    diff.field("status", &old.status, &new.status);
+    diff.field("mal_score", &old.mal_score, &new.mal_score);
+    diff.field("studios", &old.studios, &new.studios);
+    diff.field("producers", &old.producers, &new.producers);
+    diff.field("genres", &old.genres, &new.genres);
+    diff.field("sub_episodes", &old.sub_episodes, &new.sub_episodes);
+    diff.field("dub_episodes", &old.dub_episodes, &new.dub_episodes);
+    diff.field("total_episodes", &old.total_episodes, &new.total_episodes);
+    diff.field("sub_or_dub", &old.sub_or_dub, &new.sub_or_dub);
+    diff.field("duration", &old.duration, &new.duration);
+    diff.field("premiered", &old.premiered, &new.premiered);
+    diff.field("aired", &old.aired, &new.aired);
+    diff.field(
+        "duration_minutes",
+        &old.duration_minutes,
+        &new.duration_minutes,
+    );
+    diff.field(
+        "premiered_season",
+        &old.premiered_season,
+        &new.premiered_season,
+    );
+    diff.field("premiered_year", &old.premiered_year, &new.premiered_year);
+    diff.field("aired_start", &old.aired_start, &new.aired_start);
+    diff.field("aired_end", &old.aired_end, &new.aired_end);
+    diff.changes
+}
+
+// Function to list the fields of a stored episode an upsert of `new` would change
+pub fn episode_changes(old: &Episode, new: &Episode) -> Vec<FieldChange> {
+    let mut diff = Diff::default();
+    diff.field("title", &old.title, &new.title);
+    diff.field("is_filler", &old.is_filler, &new.is_filler);
+    diff.field("episode_no", &old.episode_no, &new.episode_no);
+    diff.field("anime_id", &old.anime_id, &new.anime_id);
+    diff.changes
+}
+
+// Function to record the changed fields of an anime
+pub fn record_anime_changes(
+    anime_id: i32,
+    sync_run_id: Option<i64>,
+    changes: &[FieldChange],
+    connection: &mut PgConnection,
+) -> Result<usize, DieselError> {
+    if changes.is_empty() {
+        return Ok(0);
+    }
+    let rows: Vec<_> = changes
+        .iter()
+        .map(|change| {
+            (
+                anime_history::anime_id.eq(anime_id),
+                anime_history::sync_run_id.eq(sync_run_id),
+                anime_history::field.eq(change.field),
+                anime_history::old_value.eq(&change.old_value),
+                anime_history::new_value.eq(&change.new_value),
+            )
+        })
+        .collect();
+
+    diesel::insert_into(anime_history::table)
+        .values(&rows)
+        .execute(connection)
+}
+
+// Function to record the changed fields of an episode
+pub fn record_episode_changes(
+    episode: &Episode,
+    sync_run_id: Option<i64>,
+    changes: &[FieldChange],
+    connection: &mut PgConnection,
+) -> Result<usize, DieselError> {
+    if changes.is_empty() {
+        return Ok(0);
+    }
+    let rows: Vec<_> = changes
+        .iter()
+        .map(|change| {
+            (
+                episode_history::episode_id.eq(&episode.id),
+                episode_history::anime_id.eq(episode.anime_id),
+                episode_history::sync_run_id.eq(sync_run_id),
+                episode_history::field.eq(change.field),
+                episode_history::old_value.eq(&change.old_value),
+                episode_history::new_value.eq(&change.new_value),
+            )
+        })
+        .collect();
+
+    diesel::insert_into(episode_history::table)
+        .values(&rows)
+        .execute(connection)
+}
+
+// Function to load the changes of an anime, oldest first
+pub fn load_anime_history(
+    anime_id: i32,
+    connection: &mut PgConnection,
+) -> Result<Vec<AnimeHistory>, DieselError> {
+    anime_history::table
+        .filter(anime_history::anime_id.eq(anime_id))
+        .order((anime_history::changed_at.asc(), anime_history::id.asc()))
+        .select(AnimeHistory::as_select())
+        .load(connection)
+}
+
+// Function to load the changes of the episodes of an anime, oldest first
+pub fn load_episode_history(
+    anime_id: i32,
+    connection: &mut PgConnection,
+) -> Result<Vec<EpisodeHistory>, DieselError> {
+    episode_history::table
+        .filter(episode_history::anime_id.eq(anime_id))
+        .order((episode_history::changed_at.asc(), episode_history::id.asc()))
+        .select(EpisodeHistory::as_select())
+        .load(connection)
+}
@@ -0,0 +1,95 @@
+// jikan_ops.rs
+
+use crate::db::PgPool;
+use crate::error::Error;
+use crate::operations::sync_run_ops::{RunCounts, RunRecorder};
+use crate::operations::sync_state_ops::{
+    count_sync_items, load_mal_ids_to_sync, mark_sync_done, mark_sync_failed, SyncStatus,
+};
+use crate::scheduler::Scheduler;
+use chrono::TimeDelta;
+use std::future::Future;
+
+// Run `job` once for every MAL ID in the `anime` table and checkpoint each MAL ID in
+// `pipeline`. `job` gets the MAL ID with the IDs of the anime sharing it and returns what
+// it stored, which is reported into `run`. Each pipeline is named after the entity it
+// stores, failed MAL IDs are counted against it. With `max_age`, MAL IDs synced more
+// recently than that are skipped
+pub async fn sync_mal_ids<F, Fut>(
+    pipeline: &'static str,
+    pool: &PgPool,
+    max_age: Option<TimeDelta>,
+    scheduler: &Scheduler,
+    run: &RunRecorder,
+    job: F,
+) -> Result<(), Error>
+where
+    F: Fn(i32, Vec<i32>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<RunCounts, Error>> + Send + 'static,
+{
+    let mal_ids = {
+        let mut connection = pool.get()?;
+        let (mal_ids, fresh) = load_mal_ids_to_sync(pipeline, max_age, &mut connection)?;
+        println!(
+            "{} MAL IDs to fetch {} for, {} synced recently.",
+            mal_ids.len(),
+            pipeline,
+            fresh
+        );
+        mal_ids
+    };
+
+    let job_pool = pool.clone();
+    let job_run = run.clone();
+    let results = scheduler
+        .run(mal_ids, move |(mal_id, anime_ids)| {
+            let pool = job_pool.clone();
+            let run = job_run.clone();
+            let fetch = job(mal_id, anime_ids);
+            async move {
+                let result = fetch.await;
+
+                let key = mal_id.to_string();
+                let mut connection = pool.get()?;
+                match result {
+                    Ok(counts) => {
+                        mark_sync_done(pipeline, &key, &mut connection)?;
+                        run.record(&counts);
+                        println!(
+                            "{}\t{} {}",
+                            mal_id,
+                            counts.get(pipeline).upserts.total(),
+                            pipeline
+                        );
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to fetch {} for MAL ID {}: {}", pipeline, mal_id, e);
+                        mark_sync_failed(pipeline, &key, &e.to_string(), &mut connection)?;
+                        run.record_failure(pipeline, &key, &e.to_string());
+                    }
+                }
+                Ok::<(), Error>(())
+            }
+        })
+        .await?;
+
+    for result in results {
+        if let Err(e) = result {
+            eprintln!("Task failed: {}", e);
+        }
+    }
+
+    if scheduler.is_cancelled() {
+        println!("Fetching {} cancelled.", pipeline);
+    }
+
+    let mut connection = pool.get()?;
+    println!(
+        "Fetching {} Complete. {} done, {} failed.",
+        pipeline,
+        count_sync_items(pipeline, SyncStatus::Done, &mut connection)?,
+        count_sync_items(pipeline, SyncStatus::Failed, &mut connection)?,
+    );
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+// record_ops.rs
+
+use crate::model::{Anime, AnimeStaff, Episode, Staff};
+use crate::operations::anime_ops::add_new_anime;
+use crate::operations::episode_ops::add_new_episodes;
+use crate::operations::staff_ops::{upsert_anime_staff, upsert_staff};
+use crate::operations::sync_run_ops::{
+    RunCounts, ANIME_ENTITY, ANIME_STAFF_ENTITY, EPISODES_ENTITY, STAFF_ENTITY,
+};
+use crate::operations::taxonomy_ops::set_anime_taxonomy;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use std::error::Error as StdError;
+use std::fmt;
+use std::fmt::Formatter;
+
+// Everything stored for one anime, written as a single unit
+#[derive(Debug)]
+pub struct AnimeRecord {
+    pub anime: Anime,
+    pub episodes: Vec<Episode>,
+    // Staff rows are upserted before the anime_staff links pointing at them
+    pub staff: Vec<Staff>,
+    pub anime_staff: Vec<AnimeStaff>,
+}
+
+// Part of an AnimeRecord that was being written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteStage {
+    Anime,
+    // Genres, studios and producers split from the anime's text columns
+    Taxonomy,
+    Episodes,
+    Staff,
+    AnimeStaff,
+    Transaction,
+}
+
+impl fmt::Display for WriteStage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteStage::Anime => write!(f, "anime"),
+            WriteStage::Taxonomy => write!(f, "genres, studios and producers"),
+            WriteStage::Episodes => write!(f, "episodes"),
+            WriteStage::Staff => write!(f, "staff"),
+            WriteStage::AnimeStaff => write!(f, "anime staff links"),
+            WriteStage::Transaction => write!(f, "transaction"),
+        }
+    }
+}
+
+// Error returned when an AnimeRecord was rolled back
+#[derive(Debug)]
+pub struct WriteError {
+    pub anime_id: i32,
+    pub stage: WriteStage,
+    pub source: DieselError,
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Failed to write {} of anime {}, rolled back: {}",
+            self.stage, self.anime_id, self.source
+        )
+    }
+}
+
+impl StdError for WriteError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.source)
+    }
+}
+
+// Failure inside the transaction, before the anime ID is attached
+struct StageFailure {
+    stage: WriteStage,
+    source: DieselError,
+}
+
+// Errors raised by BEGIN and COMMIT themselves
+impl From<DieselError> for StageFailure {
+    fn from(source: DieselError) -> Self {
+        StageFailure {
+            stage: WriteStage::Transaction,
+            source,
+        }
+    }
+}
+
+fn at_stage<T>(stage: WriteStage, result: Result<T, DieselError>) -> Result<T, StageFailure> {
+    result.map_err(|source| StageFailure { stage, source })
+}
+
+// Write an anime with its episodes and staff, rolling everything back on failure.
+// Changed fields are recorded in the history under `sync_run_id`. Returns what was
+// written for each entity
+pub fn write_anime_record(
+    record: AnimeRecord,
+    sync_run_id: Option<i64>,
+    connection: &mut PgConnection,
+) -> Result<RunCounts, WriteError> {
+    let anime_id = record.anime.id;
+
+    connection
+        .transaction(|connection| {
+            let mut counts = RunCounts::default();
+            counts.add(
+                ANIME_ENTITY,
+                at_stage(
+                    WriteStage::Anime,
+                    add_new_anime(&record.anime, sync_run_id, connection),
+                )?,
+            );
+            at_stage(
+                WriteStage::Taxonomy,
+                set_anime_taxonomy(&record.anime, connection),
+            )?;
+            counts.add_counts(
+                EPISODES_ENTITY,
+                at_stage(
+                    WriteStage::Episodes,
+                    add_new_episodes(&record.episodes, sync_run_id, connection),
+                )?,
+            );
+            for new_staff in &record.staff {
+                counts.add(
+                    STAFF_ENTITY,
+                    at_stage(WriteStage::Staff, upsert_staff(new_staff, connection))?,
+                );
+            }
+            for new_anime_staff in &record.anime_staff {
+                counts.add(
+                    ANIME_STAFF_ENTITY,
+                    at_stage(
+                        WriteStage::AnimeStaff,
+                        upsert_anime_staff(new_anime_staff, connection),
+                    )?,
+                );
+            }
+            Ok(counts)
+        })
+        .map_err(|failure: StageFailure| WriteError {
+            anime_id,
+            stage: failure.stage,
+            source: failure.source,
+        })
+}
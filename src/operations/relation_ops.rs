@@ -0,0 +1,219 @@
+// relation_ops.rs
+
+use crate::config::Config;
+use crate::db::PgPool;
+use crate::error::Error;
+use crate::http::{self, ClientFactory};
+use crate::model::{Anime, AnimeRelation};
+use crate::operations::anime_ops::load_anime_by_id;
+use crate::operations::history_ops::UpsertOutcome;
+use crate::operations::jikan_ops::sync_mal_ids;
+use crate::operations::sync_run_ops::{RunCounts, RunRecorder, RELATIONS_ENTITY};
+use crate::operations::sync_state_ops::RELATIONS_PIPELINE;
+use crate::retry::RetryPolicy;
+use crate::scheduler::Scheduler;
+use crate::schema::{anime, anime_relations};
+use chrono::TimeDelta;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+// Relation names as Jikan reports them
+pub const PREQUEL: &str = "Prequel";
+pub const SEQUEL: &str = "Sequel";
+pub const SIDE_STORY: &str = "Side Story";
+pub const PARENT_STORY: &str = "Parent Story";
+
+#[derive(Debug, Deserialize)]
+pub struct RelationsResponse {
+    pub data: Vec<RelationData>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RelationData {
+    relation: String,
+    entry: Vec<RelationEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RelationEntry {
+    mal_id: i32,
+    // anime or manga, only anime are stored
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+impl RelationsResponse {
+    // Relations to other anime, keeping the first relation when an anime is listed twice
+    pub fn to_anime_relations(&self, anime_table_id: i32) -> Vec<AnimeRelation> {
+        let mut seen = HashSet::new();
+        self.data
+            .iter()
+            .flat_map(|data| data.entry.iter().map(move |entry| (&data.relation, entry)))
+            .filter(|(_, entry)| entry.kind == "anime" && seen.insert(entry.mal_id))
+            .map(|(relation, entry)| AnimeRelation {
+                anime_id: anime_table_id,
+                related_mal_id: entry.mal_id,
+                relation: relation.clone(),
+            })
+            .collect()
+    }
+}
+
+// Replace the relations of every anime sharing a MAL ID with the fetched ones
+pub fn save_relations_response(
+    response: &RelationsResponse,
+    anime_ids: &[i32],
+    connection: &mut PgConnection,
+) -> Result<RunCounts, DieselError> {
+    connection.transaction(|connection| {
+        let mut counts = RunCounts::default();
+        for anime_id in anime_ids {
+            // Compare against the stored relations before they are replaced
+            let stored: HashMap<i32, String> = anime_relations::table
+                .filter(anime_relations::anime_id.eq(anime_id))
+                .select((anime_relations::related_mal_id, anime_relations::relation))
+                .load::<(i32, String)>(connection)?
+                .into_iter()
+                .collect();
+            let relations = response.to_anime_relations(*anime_id);
+            for relation in &relations {
+                let outcome = match stored.get(&relation.related_mal_id) {
+                    Some(stored) if *stored == relation.relation => UpsertOutcome::Unchanged,
+                    Some(_) => UpsertOutcome::Updated,
+                    None => UpsertOutcome::Inserted,
+                };
+                counts.add(RELATIONS_ENTITY, outcome);
+            }
+            // Relations no longer listed are removed along with the rest
+            let kept: HashSet<i32> = relations.iter().map(|r| r.related_mal_id).collect();
+            let removed = stored.keys().filter(|id| !kept.contains(id)).count();
+            counts.delete(RELATIONS_ENTITY, removed);
+
+            diesel::delete(anime_relations::table.filter(anime_relations::anime_id.eq(anime_id)))
+                .execute(connection)?;
+            diesel::insert_into(anime_relations::table)
+                .values(&relations)
+                .execute(connection)?;
+        }
+        Ok(counts)
+    })
+}
+
+// Load the stored anime an anime points to with the given relation
+pub fn load_related_anime(
+    anime_table_id: i32,
+    relation: &str,
+    connection: &mut PgConnection,
+) -> Result<Vec<Anime>, DieselError> {
+    anime_relations::table
+        .inner_join(anime::table.on(anime::mal_id.eq(anime_relations::related_mal_id)))
+        .filter(anime_relations::anime_id.eq(anime_table_id))
+        .filter(anime_relations::relation.eq(relation))
+        .order(anime::id.asc())
+        .select(Anime::as_select())
+        .load(connection)
+}
+
+// Walk the franchise of an anime in watch order: back through prequels and parent stories
+// to the first entry, then forward through sequels with each entry's side stories right
+// after it. Only stored anime are listed, the walk stops where the chain leaves the database
+pub fn load_watch_order(
+    anime_table_id: i32,
+    connection: &mut PgConnection,
+) -> Result<Vec<Anime>, DieselError> {
+    let Some(mut first) = load_anime_by_id(anime_table_id, connection)? else {
+        return Ok(vec![]);
+    };
+
+    // Relations can form cycles, every anime is visited once
+    let mut visited = HashSet::from([first.id]);
+    loop {
+        let mut earlier = load_related_anime(first.id, PREQUEL, connection)?;
+        if earlier.is_empty() {
+            earlier = load_related_anime(first.id, PARENT_STORY, connection)?;
+        }
+        match earlier
+            .into_iter()
+            .find(|anime| !visited.contains(&anime.id))
+        {
+            Some(anime) => {
+                visited.insert(anime.id);
+                first = anime;
+            }
+            None => break,
+        }
+    }
+
+    let mut order = Vec::new();
+    let mut seen = HashSet::new();
+    let mut current = Some(first);
+    while let Some(entry) = current {
+        let entry_id = entry.id;
+        seen.insert(entry_id);
+        order.push(entry);
+
+        for side_story in load_related_anime(entry_id, SIDE_STORY, connection)? {
+            if seen.insert(side_story.id) {
+                order.push(side_story);
+            }
+        }
+
+        current = load_related_anime(entry_id, SEQUEL, connection)?
+            .into_iter()
+            .find(|anime| !seen.contains(&anime.id));
+    }
+
+    Ok(order)
+}
+
+pub async fn fetch_jikan_relations_response(
+    config: &Config,
+    anime_mal_id: i32,
+) -> Result<RelationsResponse, Error> {
+    let client = ClientFactory::global().api()?;
+    let relations_url = format!("{}/anime/{}/relations", config.jikan_api_url, anime_mal_id);
+    RetryPolicy::from_config(config)
+        .run(|| async {
+            let response = http::get(&client, &relations_url).await?;
+            http::read_json(&relations_url, response).await
+        })
+        .await
+}
+
+// Fetch and store the relations of every anime with a MAL ID. With `max_age`, MAL IDs
+// whose relations were synced more recently than that are skipped
+pub async fn store_relation_data(
+    config: &Config,
+    pool: &PgPool,
+    max_age: Option<TimeDelta>,
+    scheduler: &Scheduler,
+    run: &RunRecorder,
+) -> Result<(), Error> {
+    let config = config.clone();
+    let job_pool = pool.clone();
+
+    sync_mal_ids(
+        RELATIONS_PIPELINE,
+        pool,
+        max_age,
+        scheduler,
+        run,
+        move |mal_id, anime_ids| {
+            let config = config.clone();
+            let pool = job_pool.clone();
+            async move {
+                let response = fetch_jikan_relations_response(&config, mal_id).await?;
+                let mut connection = pool.get()?;
+                Ok(save_relations_response(
+                    &response,
+                    &anime_ids,
+                    &mut connection,
+                )?)
+            }
+        },
+    )
+    .await
+}
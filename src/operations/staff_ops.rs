@@ -1,21 +1,33 @@
-use std::env;
-
-use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, RunQueryDsl};
-use dotenvy::dotenv;
-use reqwest::Client;
+use chrono::TimeDelta;
+use diesel::dsl::now;
+use diesel::pg::PgConnection;
+use diesel::query_dsl::methods::{FilterDsl, FindDsl};
+use diesel::result::Error as DieselError;
+use diesel::sql_types::{Array, Nullable, Text};
+use diesel::upsert::excluded;
+use diesel::{
+    define_sql_function, BoolExpressionMethods, Connection, ExpressionMethods, OptionalExtension,
+    PgExpressionMethods, RunQueryDsl,
+};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    db::establish_connection,
+    config::Config,
+    db::PgPool,
+    error::Error,
+    http::{self, ClientFactory},
     model::{AnimeStaff, Staff},
-    schema::anime_staff::dsl::{
-        anime_id as anime_staff_anime_id, positions as anime_staff_positions,
-        staff_id as anime_staff_staff_id,
-    },
-    schema::staff::dsl::{mal_id as staff_mal_id, positions as staff_positions},
+    operations::history_ops::{inserted_row, upsert_outcome, UpsertOutcome},
+    operations::jikan_ops::sync_mal_ids,
+    operations::sync_run_ops::{RunCounts, RunRecorder, ANIME_STAFF_ENTITY, STAFF_ENTITY},
+    operations::sync_state_ops::STAFF_PIPELINE,
+    retry::RetryPolicy,
+    scheduler::Scheduler,
+    schema::{anime_staff, staff},
 };
 
-use super::anime_ops::CustomError;
+// Union of two position arrays, defined by the array_union migration
+define_sql_function!(fn array_union(a: Array<Nullable<Text>>, b: Array<Nullable<Text>>) -> Array<Nullable<Text>>);
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StaffResponse {
@@ -29,15 +41,15 @@ pub struct PersonData {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct Person {
-    mal_id: i32,
+pub(crate) struct Person {
+    pub(crate) mal_id: i32,
     url: String,
     images: Images,
     name: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct Images {
+pub(crate) struct Images {
     jpg: JpgImage,
 }
 
@@ -46,52 +58,47 @@ struct JpgImage {
     image_url: String,
 }
 
-// Refactored function for inserting or updating staff
-pub fn insert_or_update_staff(staff_data: &PersonData) -> Result<(), CustomError> {
-    let mut connection = establish_connection();
-
-    // Check if the staff already exists
-    let staff_exists = diesel::select(diesel::dsl::exists(
-        crate::schema::staff::table.filter(staff_mal_id.eq(&staff_data.person.mal_id)),
-    ))
-    .get_result::<bool>(&mut connection)?;
-
-    if staff_exists {
-        // Get existing positions
-        let mut existing_positions: Vec<Option<String>> = crate::schema::staff::table
-            .filter(staff_mal_id.eq(&staff_data.person.mal_id))
-            .select(staff_positions)
-            .get_result::<Vec<Option<String>>>(&mut connection)?;
-
-        // Merge new positions with existing positions
-        let new_positions = convert_vec_string_to_vec_option_string(staff_data.positions.clone());
-        for position in new_positions {
-            if !existing_positions.contains(&position) {
-                existing_positions.push(position);
-            }
+impl Images {
+    pub(crate) fn jpg_url(&self) -> &str {
+        &self.jpg.image_url
+    }
+}
+
+impl Person {
+    pub(crate) fn to_staff(&self, positions: Vec<Option<String>>) -> Staff {
+        Staff {
+            mal_id: self.mal_id,
+            name: self.name.clone(),
+            mal_url: self.url.clone(),
+            image: self.images.jpg_url().to_string(),
+            positions,
         }
+    }
+}
 
-        // Update the staff record with new positions
-        diesel::update(
-            crate::schema::staff::table.filter(staff_mal_id.eq(&staff_data.person.mal_id)),
-        )
-        .set(staff_positions.eq(existing_positions))
-        .execute(&mut connection)?;
-    } else {
-        // Insert into staff if not exists
-        let new_staff = Staff {
-            mal_id: staff_data.person.mal_id,
-            name: staff_data.person.name.clone(),
-            mal_url: staff_data.person.url.clone(),
-            image: staff_data.person.images.jpg.image_url.clone(),
-            positions: convert_vec_string_to_vec_option_string(staff_data.positions.clone()),
-        };
-
-        diesel::insert_into(crate::schema::staff::table)
-            .values(&new_staff)
-            .execute(&mut connection)?;
+impl PersonData {
+    pub fn to_staff(&self) -> Staff {
+        self.person
+            .to_staff(convert_vec_string_to_vec_option_string(
+                self.positions.clone(),
+            ))
     }
 
+    pub fn to_anime_staff(&self, anime_table_id: i32) -> AnimeStaff {
+        AnimeStaff {
+            anime_id: anime_table_id,
+            staff_id: self.person.mal_id,
+            positions: convert_vec_string_to_vec_option_string(self.positions.clone()),
+        }
+    }
+}
+
+// Refactored function for inserting or updating staff
+pub fn insert_or_update_staff(
+    staff_data: &PersonData,
+    connection: &mut PgConnection,
+) -> Result<(), Error> {
+    upsert_staff(&staff_data.to_staff(), connection)?;
     Ok(())
 }
 
@@ -99,58 +106,171 @@ pub fn insert_or_update_staff(staff_data: &PersonData) -> Result<(), CustomError
 pub fn insert_into_anime_staff(
     staff_data: &PersonData,
     anime_table_id: i32,
-) -> Result<(), CustomError> {
-    let mut connection = establish_connection();
-
-    // Check if the anime_staff relationship already exists
-    let anime_staff_exists = diesel::select(diesel::dsl::exists(
-        crate::schema::anime_staff::table.filter(
-            anime_staff_staff_id
-                .eq(&staff_data.person.mal_id)
-                .and(anime_staff_anime_id.eq(&anime_table_id)),
-        ),
-    ))
-    .get_result::<bool>(&mut connection)?;
-
-    if !anime_staff_exists {
-        // Insert into anime_staff if not exists
-        let new_anime_staff = AnimeStaff {
-            anime_id: anime_table_id,
-            staff_id: staff_data.person.mal_id,
-            positions: convert_vec_string_to_vec_option_string(staff_data.positions.clone()),
-        };
+    connection: &mut PgConnection,
+) -> Result<(), Error> {
+    upsert_anime_staff(&staff_data.to_anime_staff(anime_table_id), connection)?;
+    Ok(())
+}
 
-        diesel::insert_into(crate::schema::anime_staff::table)
-            .values(&new_anime_staff)
-            .execute(&mut connection)?;
+// Insert a staff, or refresh it and merge the positions when it exists
+pub fn upsert_staff(
+    new_staff: &Staff,
+    connection: &mut PgConnection,
+) -> Result<UpsertOutcome, DieselError> {
+    let inserted = diesel::insert_into(staff::table)
+        .values(new_staff)
+        .on_conflict(staff::mal_id)
+        .do_update()
+        .set((
+            staff::name.eq(excluded(staff::name)),
+            staff::mal_url.eq(excluded(staff::mal_url)),
+            staff::image.eq(excluded(staff::image)),
+            staff::positions.eq(array_union(staff::positions, excluded(staff::positions))),
+            staff::updated_at.eq(now),
+            staff::last_fetched_at.eq(now),
+        ))
+        // Leave the row alone when it already holds every value
+        .filter(
+            staff::name
+                .is_distinct_from(excluded(staff::name))
+                .or(staff::mal_url.is_distinct_from(excluded(staff::mal_url)))
+                .or(staff::image.is_distinct_from(excluded(staff::image)))
+                .or(array_union(staff::positions, excluded(staff::positions))
+                    .is_distinct_from(staff::positions)),
+        )
+        .returning(inserted_row())
+        .get_result::<bool>(connection)
+        .optional()?;
+
+    // The filter skipped the update, the staff was still fetched
+    if inserted.is_none() {
+        diesel::update(staff::table.find(new_staff.mal_id))
+            .set(staff::last_fetched_at.eq(now))
+            .execute(connection)?;
     }
 
+    Ok(upsert_outcome(inserted))
+}
+
+// Insert a staff unless it exists, keeping the row and positions already stored
+pub fn insert_staff_if_missing(
+    new_staff: &Staff,
+    connection: &mut PgConnection,
+) -> Result<(), DieselError> {
+    diesel::insert_into(staff::table)
+        .values(new_staff)
+        .on_conflict(staff::mal_id)
+        .do_nothing()
+        .execute(connection)?;
+
     Ok(())
 }
 
-pub async fn fetch_jikan_staff_response(anime_mal_id: u16) -> Result<StaffResponse, CustomError> {
-    dotenv().ok();
-    let jikan_api_url = env::var("JIKAN_API_URL").expect("JIKAN_API_URL must be set.");
+// Insert an anime_staff link, or merge the positions when it exists
+pub fn upsert_anime_staff(
+    new_anime_staff: &AnimeStaff,
+    connection: &mut PgConnection,
+) -> Result<UpsertOutcome, DieselError> {
+    let inserted = diesel::insert_into(anime_staff::table)
+        .values(new_anime_staff)
+        .on_conflict((anime_staff::anime_id, anime_staff::staff_id))
+        .do_update()
+        .set((
+            anime_staff::positions.eq(array_union(
+                anime_staff::positions,
+                excluded(anime_staff::positions),
+            )),
+            anime_staff::updated_at.eq(now),
+            anime_staff::last_fetched_at.eq(now),
+        ))
+        // Leave the link alone when it already has every position
+        .filter(
+            array_union(anime_staff::positions, excluded(anime_staff::positions))
+                .is_distinct_from(anime_staff::positions),
+        )
+        .returning(inserted_row())
+        .get_result::<bool>(connection)
+        .optional()?;
 
-    let client = Client::new();
-    let staff_url = format!("{}/anime/{}/staff", jikan_api_url, anime_mal_id);
-    let response = client.get(staff_url).send().await;
+    // The filter skipped the update, the link was still fetched
+    if inserted.is_none() {
+        diesel::update(
+            anime_staff::table.find((new_anime_staff.anime_id, new_anime_staff.staff_id)),
+        )
+        .set(anime_staff::last_fetched_at.eq(now))
+        .execute(connection)?;
+    }
+
+    Ok(upsert_outcome(inserted))
+}
+
+pub async fn fetch_jikan_staff_response(
+    config: &Config,
+    anime_mal_id: i32,
+) -> Result<StaffResponse, Error> {
+    let client = ClientFactory::global().api()?;
+    let staff_url = format!("{}/anime/{}/staff", config.jikan_api_url, anime_mal_id);
+    RetryPolicy::from_config(config)
+        .run(|| async {
+            let response = http::get(&client, &staff_url).await?;
+            http::read_json(&staff_url, response).await
+        })
+        .await
+}
 
-    match response {
-        Ok(res) => {
-            if res.status().is_success() {
-                let staff_data = res.json().await?;
-                return Ok(staff_data);
-            } else {
-                eprintln!("Failed with status: {:?}", res.status());
+// Store the staff of one MAL ID and link it to every anime sharing that MAL ID
+pub fn save_staff_response(
+    response: &StaffResponse,
+    anime_ids: &[i32],
+    connection: &mut PgConnection,
+) -> Result<RunCounts, DieselError> {
+    connection.transaction(|connection| {
+        let mut counts = RunCounts::default();
+        for person in &response.data {
+            // Staff rows must exist before they can be linked
+            counts.add(STAFF_ENTITY, upsert_staff(&person.to_staff(), connection)?);
+            for anime_id in anime_ids {
+                counts.add(
+                    ANIME_STAFF_ENTITY,
+                    upsert_anime_staff(&person.to_anime_staff(*anime_id), connection)?,
+                );
             }
         }
-        Err(_e) => eprintln!("Failed to fetch staff data for {}.", anime_mal_id),
-    }
+        Ok(counts)
+    })
+}
 
-    Err(CustomError::FailedToFetchAfterRetries)
+// Fetch and store the staff of every anime with a MAL ID. With `max_age`, MAL IDs
+// whose staff were synced more recently than that are skipped
+pub async fn store_staff_data(
+    config: &Config,
+    pool: &PgPool,
+    max_age: Option<TimeDelta>,
+    scheduler: &Scheduler,
+    run: &RunRecorder,
+) -> Result<(), Error> {
+    let config = config.clone();
+    let job_pool = pool.clone();
+
+    sync_mal_ids(
+        STAFF_PIPELINE,
+        pool,
+        max_age,
+        scheduler,
+        run,
+        move |mal_id, anime_ids| {
+            let config = config.clone();
+            let pool = job_pool.clone();
+            async move {
+                let response = fetch_jikan_staff_response(&config, mal_id).await?;
+                let mut connection = pool.get()?;
+                Ok(save_staff_response(&response, &anime_ids, &mut connection)?)
+            }
+        },
+    )
+    .await
 }
 
 pub fn convert_vec_string_to_vec_option_string(strings: Vec<String>) -> Vec<Option<String>> {
-    strings.into_iter().map(|s| Some(s)).collect()
+    strings.into_iter().map(Some).collect()
 }
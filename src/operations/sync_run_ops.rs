@@ -0,0 +1,278 @@
+// sync_run_ops.rs
+
+use crate::model::{SyncRun, SyncRunCount, SyncRunError};
+use crate::operations::history_ops::{UpsertCounts, UpsertOutcome};
+use crate::schema::{sync_run_counts, sync_run_errors, sync_runs};
+use diesel::dsl::now;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+// Entities the pipelines report counts for, `anime_ids` being the names of the A-Z list
+pub const ANIME_IDS_ENTITY: &str = "anime_ids";
+pub const ANIME_ENTITY: &str = "anime";
+pub const EPISODES_ENTITY: &str = "episodes";
+pub const STAFF_ENTITY: &str = "staff";
+pub const ANIME_STAFF_ENTITY: &str = "anime_staff";
+pub const CHARACTERS_ENTITY: &str = "characters";
+pub const ANIME_CHARACTERS_ENTITY: &str = "anime_characters";
+pub const VOICE_ACTORS_ENTITY: &str = "voice_actors";
+pub const RELATIONS_ENTITY: &str = "relations";
+pub const ENRICHMENT_ENTITY: &str = "enrichment";
+
+// Errors kept per run, later ones are only counted
+const MAX_ERROR_SAMPLES: usize = 20;
+
+// Status of a sync run in the ledger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunStatus {
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl RunStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunStatus::Running => "running",
+            RunStatus::Succeeded => "succeeded",
+            RunStatus::Failed => "failed",
+            RunStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+// Rows a run wrote or deleted and items it failed on for one entity
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EntityCounts {
+    pub upserts: UpsertCounts,
+    pub failed: usize,
+    // Rows removed because they were gone from the source
+    pub deleted: usize,
+}
+
+// Counts of every entity a pipeline touched, keyed by entity such as `anime` or `staff`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RunCounts {
+    entities: BTreeMap<&'static str, EntityCounts>,
+}
+
+impl RunCounts {
+    pub fn add(&mut self, entity: &'static str, outcome: UpsertOutcome) {
+        self.entities
+            .entry(entity)
+            .or_default()
+            .upserts
+            .add(outcome);
+    }
+
+    pub fn add_counts(&mut self, entity: &'static str, counts: UpsertCounts) {
+        self.entities
+            .entry(entity)
+            .or_default()
+            .upserts
+            .merge(counts);
+    }
+
+    pub fn fail(&mut self, entity: &'static str) {
+        self.entities.entry(entity).or_default().failed += 1;
+    }
+
+    pub fn delete(&mut self, entity: &'static str, deleted: usize) {
+        self.entities.entry(entity).or_default().deleted += deleted;
+    }
+
+    pub fn merge(&mut self, other: &RunCounts) {
+        for (entity, counts) in &other.entities {
+            let entry = self.entities.entry(entity).or_default();
+            entry.upserts.merge(counts.upserts);
+            entry.failed += counts.failed;
+            entry.deleted += counts.deleted;
+        }
+    }
+
+    pub fn get(&self, entity: &str) -> EntityCounts {
+        self.entities.get(entity).copied().unwrap_or_default()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, EntityCounts)> + '_ {
+        self.entities
+            .iter()
+            .map(|(entity, counts)| (*entity, *counts))
+    }
+}
+
+// An error kept to show what went wrong in a run
+#[derive(Debug, Clone)]
+struct ErrorSample {
+    entity: &'static str,
+    item_key: String,
+    message: String,
+}
+
+#[derive(Debug, Default)]
+struct Ledger {
+    counts: RunCounts,
+    errors: Vec<ErrorSample>,
+}
+
+// Collects what the pipelines of one sync run did until it is written by `finish_sync_run`.
+// Clones share the same counts, so every job of a pipeline can report into it
+#[derive(Debug, Clone)]
+pub struct RunRecorder {
+    id: i64,
+    ledger: Arc<Mutex<Ledger>>,
+}
+
+impl RunRecorder {
+    // ID of the run, history rows written by the run carry it
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn record(&self, counts: &RunCounts) {
+        self.ledger.lock().unwrap().counts.merge(counts);
+    }
+
+    // Count a failed item and keep its error while there is room for samples
+    pub fn record_failure(&self, entity: &'static str, item_key: &str, message: &str) {
+        let mut ledger = self.ledger.lock().unwrap();
+        ledger.counts.fail(entity);
+        if ledger.errors.len() < MAX_ERROR_SAMPLES {
+            ledger.errors.push(ErrorSample {
+                entity,
+                item_key: item_key.to_string(),
+                message: message.to_string(),
+            });
+        }
+    }
+
+    pub fn counts(&self) -> RunCounts {
+        self.ledger.lock().unwrap().counts.clone()
+    }
+}
+
+// Function to open a run in the ledger for the given command line
+pub fn start_sync_run(
+    command: &str,
+    connection: &mut PgConnection,
+) -> Result<RunRecorder, DieselError> {
+    let id = diesel::insert_into(sync_runs::table)
+        .values(sync_runs::command.eq(command))
+        .returning(sync_runs::id)
+        .get_result(connection)?;
+
+    Ok(RunRecorder {
+        id,
+        ledger: Arc::default(),
+    })
+}
+
+// Function to close a run, writing its status and everything its pipelines reported. A run
+// that was closed already, such as by a Ctrl-C aborting the process, is left as it is
+pub fn finish_sync_run(
+    run: &RunRecorder,
+    status: RunStatus,
+    error: Option<&str>,
+    connection: &mut PgConnection,
+) -> Result<(), DieselError> {
+    let (counts, errors) = {
+        let ledger = run.ledger.lock().unwrap();
+        (ledger.counts.clone(), ledger.errors.clone())
+    };
+
+    connection.transaction(|connection| {
+        let closed = diesel::update(
+            sync_runs::table
+                .find(run.id)
+                .filter(sync_runs::status.eq(RunStatus::Running.as_str())),
+        )
+        .set((
+            sync_runs::status.eq(status.as_str()),
+            sync_runs::error.eq(error),
+            sync_runs::finished_at.eq(now),
+        ))
+        .execute(connection)?;
+        if closed == 0 {
+            return Ok(());
+        }
+
+        let count_rows: Vec<_> = counts
+            .iter()
+            .map(|(entity, counts)| {
+                (
+                    sync_run_counts::sync_run_id.eq(run.id),
+                    sync_run_counts::entity.eq(entity),
+                    sync_run_counts::inserted.eq(counts.upserts.inserted as i64),
+                    sync_run_counts::updated.eq(counts.upserts.updated as i64),
+                    sync_run_counts::unchanged.eq(counts.upserts.unchanged as i64),
+                    sync_run_counts::failed.eq(counts.failed as i64),
+                    sync_run_counts::deleted.eq(counts.deleted as i64),
+                )
+            })
+            .collect();
+        if !count_rows.is_empty() {
+            diesel::insert_into(sync_run_counts::table)
+                .values(&count_rows)
+                .execute(connection)?;
+        }
+
+        let error_rows: Vec<_> = errors
+            .iter()
+            .map(|sample| {
+                (
+                    sync_run_errors::sync_run_id.eq(run.id),
+                    sync_run_errors::entity.eq(sample.entity),
+                    sync_run_errors::item_key.eq(&sample.item_key),
+                    sync_run_errors::message.eq(&sample.message),
+                )
+            })
+            .collect();
+        if !error_rows.is_empty() {
+            diesel::insert_into(sync_run_errors::table)
+                .values(&error_rows)
+                .execute(connection)?;
+        }
+
+        Ok(())
+    })
+}
+
+// Function to load the most recent runs, newest first
+pub fn load_recent_sync_runs(
+    limit: i64,
+    connection: &mut PgConnection,
+) -> Result<Vec<SyncRun>, DieselError> {
+    sync_runs::table
+        .order(sync_runs::id.desc())
+        .limit(limit)
+        .select(SyncRun::as_select())
+        .load(connection)
+}
+
+// Function to load the counts of a run, one row per entity
+pub fn load_sync_run_counts(
+    sync_run_id: i64,
+    connection: &mut PgConnection,
+) -> Result<Vec<SyncRunCount>, DieselError> {
+    sync_run_counts::table
+        .filter(sync_run_counts::sync_run_id.eq(sync_run_id))
+        .order(sync_run_counts::entity.asc())
+        .select(SyncRunCount::as_select())
+        .load(connection)
+}
+
+// Function to load the error samples of a run in the order they happened
+pub fn load_sync_run_errors(
+    sync_run_id: i64,
+    connection: &mut PgConnection,
+) -> Result<Vec<SyncRunError>, DieselError> {
+    sync_run_errors::table
+        .filter(sync_run_errors::sync_run_id.eq(sync_run_id))
+        .order(sync_run_errors::id.asc())
+        .select(SyncRunError::as_select())
+        .load(connection)
+}
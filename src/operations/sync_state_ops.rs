@@ -0,0 +1,263 @@
+// sync_state_ops.rs
+
+use crate::model::SyncState;
+use crate::operations::anime_ops::load_anime_mal_ids;
+use crate::schema::sync_state;
+use chrono::{DateTime, TimeDelta, Utc};
+use diesel::dsl::now;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use std::collections::{BTreeMap, HashSet};
+
+// Pipeline fetching anime details and episodes, keyed by anime name
+pub const DETAILS_PIPELINE: &str = "details";
+// Pipeline fetching staff from Jikan, keyed by MAL ID
+pub const STAFF_PIPELINE: &str = "staff";
+// Pipeline fetching characters and voice actors from Jikan, keyed by MAL ID
+pub const CHARACTERS_PIPELINE: &str = "characters";
+// Pipeline fetching relations between anime from Jikan, keyed by MAL ID
+pub const RELATIONS_PIPELINE: &str = "relations";
+
+// Rows inserted per statement, 2 bind parameters each
+const QUEUE_BATCH_SIZE: usize = 10_000;
+
+// A MAL ID with the IDs of every anime sharing it
+pub type MalIdAnime = (i32, Vec<i32>);
+
+// Status of an item in a sync pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncStatus {
+    Pending,
+    Done,
+    Failed,
+}
+
+impl SyncStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncStatus::Pending => "pending",
+            SyncStatus::Done => "done",
+            SyncStatus::Failed => "failed",
+        }
+    }
+}
+
+// Which items a sync run picks up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncMode {
+    // Queue new items and process everything still pending
+    #[default]
+    Resume,
+    // Only process items whose last attempt failed
+    RetryFailed,
+    // Put every item back to pending and process them all
+    Restart,
+    // Put the items not fetched within the given age back to pending, then resume
+    Stale(TimeDelta),
+}
+
+// Queue items as pending, leaving already known items untouched
+pub fn queue_sync_items(
+    pipeline: &str,
+    item_keys: &[String],
+    connection: &mut PgConnection,
+) -> Result<usize, DieselError> {
+    let mut queued = 0;
+    for chunk in item_keys.chunks(QUEUE_BATCH_SIZE) {
+        let rows: Vec<_> = chunk
+            .iter()
+            .map(|item_key| {
+                (
+                    sync_state::pipeline.eq(pipeline),
+                    sync_state::item_key.eq(item_key),
+                )
+            })
+            .collect();
+
+        queued += diesel::insert_into(sync_state::table)
+            .values(&rows)
+            .on_conflict_do_nothing()
+            .execute(connection)?;
+    }
+
+    Ok(queued)
+}
+
+// Load the keys of every item of a pipeline with the given status
+pub fn load_sync_items(
+    pipeline: &str,
+    status: SyncStatus,
+    connection: &mut PgConnection,
+) -> Result<Vec<String>, DieselError> {
+    sync_state::table
+        .filter(sync_state::pipeline.eq(pipeline))
+        .filter(sync_state::status.eq(status.as_str()))
+        .order(sync_state::item_key.asc())
+        .select(sync_state::item_key)
+        .load(connection)
+}
+
+// Load the keys of every item of a pipeline that reached the given status after `since`
+pub fn load_sync_items_updated_since(
+    pipeline: &str,
+    status: SyncStatus,
+    since: DateTime<Utc>,
+    connection: &mut PgConnection,
+) -> Result<Vec<String>, DieselError> {
+    sync_state::table
+        .filter(sync_state::pipeline.eq(pipeline))
+        .filter(sync_state::status.eq(status.as_str()))
+        .filter(sync_state::updated_at.gt(since))
+        .select(sync_state::item_key)
+        .load(connection)
+}
+
+// Group the anime with a MAL ID by MAL ID, leaving out the MAL IDs a pipeline finished
+// within `max_age`. Anime sharing a MAL ID share its Jikan data, so each is fetched once
+pub fn load_mal_ids_to_sync(
+    pipeline: &str,
+    max_age: Option<TimeDelta>,
+    connection: &mut PgConnection,
+) -> Result<(Vec<MalIdAnime>, usize), DieselError> {
+    let fresh: HashSet<String> = match max_age {
+        Some(max_age) => load_sync_items_updated_since(
+            pipeline,
+            SyncStatus::Done,
+            Utc::now() - max_age,
+            connection,
+        )?
+        .into_iter()
+        .collect(),
+        None => HashSet::new(),
+    };
+
+    let mut mal_ids: BTreeMap<i32, Vec<i32>> = BTreeMap::new();
+    for (mal_id, anime_id) in load_anime_mal_ids(connection)? {
+        if !fresh.contains(&mal_id.to_string()) {
+            mal_ids.entry(mal_id).or_default().push(anime_id);
+        }
+    }
+
+    Ok((mal_ids.into_iter().collect(), fresh.len()))
+}
+
+// Load the state of a single item
+pub fn load_sync_state(
+    pipeline: &str,
+    item_key: &str,
+    connection: &mut PgConnection,
+) -> Result<Option<SyncState>, DieselError> {
+    sync_state::table
+        .find((pipeline, item_key))
+        .select(SyncState::as_select())
+        .first(connection)
+        .optional()
+}
+
+// Count the items of a pipeline with the given status
+pub fn count_sync_items(
+    pipeline: &str,
+    status: SyncStatus,
+    connection: &mut PgConnection,
+) -> Result<i64, DieselError> {
+    sync_state::table
+        .filter(sync_state::pipeline.eq(pipeline))
+        .filter(sync_state::status.eq(status.as_str()))
+        .count()
+        .get_result(connection)
+}
+
+pub fn mark_sync_done(
+    pipeline: &str,
+    item_key: &str,
+    connection: &mut PgConnection,
+) -> Result<(), DieselError> {
+    record_attempt(pipeline, item_key, SyncStatus::Done, None, connection)
+}
+
+pub fn mark_sync_failed(
+    pipeline: &str,
+    item_key: &str,
+    error: &str,
+    connection: &mut PgConnection,
+) -> Result<(), DieselError> {
+    record_attempt(
+        pipeline,
+        item_key,
+        SyncStatus::Failed,
+        Some(error),
+        connection,
+    )
+}
+
+// Record the outcome of an attempt, queueing the item if it is not known yet
+fn record_attempt(
+    pipeline: &str,
+    item_key: &str,
+    status: SyncStatus,
+    error: Option<&str>,
+    connection: &mut PgConnection,
+) -> Result<(), DieselError> {
+    diesel::insert_into(sync_state::table)
+        .values((
+            sync_state::pipeline.eq(pipeline),
+            sync_state::item_key.eq(item_key),
+            sync_state::status.eq(status.as_str()),
+            sync_state::attempts.eq(1),
+            sync_state::last_error.eq(error),
+        ))
+        .on_conflict((sync_state::pipeline, sync_state::item_key))
+        .do_update()
+        .set((
+            sync_state::status.eq(status.as_str()),
+            sync_state::attempts.eq(sync_state::attempts + 1),
+            sync_state::last_error.eq(error),
+            sync_state::updated_at.eq(now),
+        ))
+        .execute(connection)?;
+
+    Ok(())
+}
+
+// Put every item of a pipeline back to pending for a full run
+pub fn reset_sync_items(
+    pipeline: &str,
+    connection: &mut PgConnection,
+) -> Result<usize, DieselError> {
+    diesel::update(sync_state::table.filter(sync_state::pipeline.eq(pipeline)))
+        .set((
+            sync_state::status.eq(SyncStatus::Pending.as_str()),
+            sync_state::attempts.eq(0),
+            sync_state::last_error.eq(None::<String>),
+            sync_state::updated_at.eq(now),
+        ))
+        .execute(connection)
+}
+
+// Put the given items of a pipeline back to pending, queueing the ones not known yet
+pub fn requeue_sync_items(
+    pipeline: &str,
+    item_keys: &[String],
+    connection: &mut PgConnection,
+) -> Result<usize, DieselError> {
+    queue_sync_items(pipeline, item_keys, connection)?;
+
+    let mut requeued = 0;
+    for chunk in item_keys.chunks(QUEUE_BATCH_SIZE) {
+        requeued += diesel::update(
+            sync_state::table
+                .filter(sync_state::pipeline.eq(pipeline))
+                .filter(sync_state::item_key.eq_any(chunk)),
+        )
+        .set((
+            sync_state::status.eq(SyncStatus::Pending.as_str()),
+            sync_state::attempts.eq(0),
+            sync_state::last_error.eq(None::<String>),
+            sync_state::updated_at.eq(now),
+        ))
+        .execute(connection)?;
+    }
+
+    Ok(requeued)
+}
@@ -0,0 +1,165 @@
+// taxonomy_ops.rs
+
+use crate::model::{Anime, Genre, Producer, Studio};
+use crate::schema::{
+    anime, anime_genres, anime_producers, anime_studios, genres, producers, studios,
+};
+use diesel::pg::{Pg, PgConnection};
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+
+// Filters for loading anime by their genres, studios and producers, all of which must match
+#[derive(Debug, Clone, Default)]
+pub struct AnimeFilter {
+    pub genre: Option<String>,
+    pub studio: Option<String>,
+    pub producer: Option<String>,
+}
+
+// Split a comma separated list as joined by the api, trimming names and dropping
+// empty and repeated ones
+pub fn split_names(value: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for name in value.split(',').map(str::trim) {
+        if !name.is_empty() && !names.iter().any(|known| known == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+// Declare the functions of one kind of name linked to anime through a join table, such as
+// genres: replacing and loading the names of an anime, narrowing a query of anime to the
+// ones linked to a name, and loading those anime
+macro_rules! taxonomy {
+    (
+        $field:ident, $model:ident, $lookup:ident, $join:ident, $link_id:ident,
+        $set_fn:ident, $load_fn:ident, $filter_fn:ident, $by_fn:ident
+    ) => {
+        // Replace the names of an anime, adding names not seen before to the lookup table
+        pub fn $set_fn(
+            anime_table_id: i32,
+            names: &[String],
+            connection: &mut PgConnection,
+        ) -> Result<(), DieselError> {
+            let rows: Vec<_> = names.iter().map(|name| $lookup::name.eq(name)).collect();
+            diesel::insert_into($lookup::table)
+                .values(&rows)
+                .on_conflict_do_nothing()
+                .execute(connection)?;
+            let ids: Vec<i32> = $lookup::table
+                .filter($lookup::name.eq_any(names))
+                .select($lookup::id)
+                .load(connection)?;
+
+            diesel::delete($join::table.filter($join::anime_id.eq(anime_table_id)))
+                .execute(connection)?;
+            let links: Vec<_> = ids
+                .into_iter()
+                .map(|id| ($join::anime_id.eq(anime_table_id), $join::$link_id.eq(id)))
+                .collect();
+            diesel::insert_into($join::table)
+                .values(&links)
+                .execute(connection)?;
+
+            Ok(())
+        }
+
+        pub fn $load_fn(
+            anime_table_id: i32,
+            connection: &mut PgConnection,
+        ) -> Result<Vec<$model>, DieselError> {
+            $join::table
+                .inner_join($lookup::table)
+                .filter($join::anime_id.eq(anime_table_id))
+                .order($lookup::name.asc())
+                .select($model::as_select())
+                .load(connection)
+        }
+
+        fn $filter_fn<'a>(
+            query: anime::BoxedQuery<'a, Pg>,
+            name: &'a str,
+        ) -> anime::BoxedQuery<'a, Pg> {
+            query.filter(
+                anime::id.eq_any(
+                    $join::table
+                        .inner_join($lookup::table)
+                        .filter($lookup::name.eq(name))
+                        .select($join::anime_id),
+                ),
+            )
+        }
+
+        pub fn $by_fn(
+            name: &str,
+            connection: &mut PgConnection,
+        ) -> Result<Vec<Anime>, DieselError> {
+            let filter = AnimeFilter {
+                $field: Some(name.to_string()),
+                ..AnimeFilter::default()
+            };
+            load_anime_matching(&filter, connection)
+        }
+    };
+}
+
+taxonomy!(
+    genre,
+    Genre,
+    genres,
+    anime_genres,
+    genre_id,
+    set_anime_genres,
+    load_anime_genres,
+    filter_by_genre,
+    anime_by_genre
+);
+taxonomy!(
+    studio,
+    Studio,
+    studios,
+    anime_studios,
+    studio_id,
+    set_anime_studios,
+    load_anime_studios,
+    filter_by_studio,
+    anime_by_studio
+);
+taxonomy!(
+    producer,
+    Producer,
+    producers,
+    anime_producers,
+    producer_id,
+    set_anime_producers,
+    load_anime_producers,
+    filter_by_producer,
+    anime_by_producer
+);
+
+// Link an anime to the genres, studios and producers listed in its text columns
+pub fn set_anime_taxonomy(anime: &Anime, connection: &mut PgConnection) -> Result<(), DieselError> {
+    set_anime_genres(anime.id, &split_names(&anime.genres), connection)?;
+    set_anime_studios(anime.id, &split_names(&anime.studios), connection)?;
+    set_anime_producers(anime.id, &split_names(&anime.producers), connection)?;
+    Ok(())
+}
+
+// Load the anime matching every filter that is set, ordered by ID
+pub fn load_anime_matching(
+    filter: &AnimeFilter,
+    connection: &mut PgConnection,
+) -> Result<Vec<Anime>, DieselError> {
+    let mut query = anime::table.order(anime::id.asc()).into_boxed();
+    if let Some(genre) = &filter.genre {
+        query = filter_by_genre(query, genre);
+    }
+    if let Some(studio) = &filter.studio {
+        query = filter_by_studio(query, studio);
+    }
+    if let Some(producer) = &filter.producer {
+        query = filter_by_producer(query, producer);
+    }
+    query.select(Anime::as_select()).load(connection)
+}
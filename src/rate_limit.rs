@@ -0,0 +1,196 @@
+// rate_limit.rs
+
+use crate::config::Config;
+use reqwest::Url;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::time::Instant;
+
+// A limit of `requests` per `per`, written as `3/s`, `60/min` or `1000/h`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rate {
+    pub requests: u32,
+    pub per: Duration,
+}
+
+impl FromStr for Rate {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (requests, unit) = value
+            .trim()
+            .split_once('/')
+            .ok_or_else(|| format!("`{}` is not of the form <requests>/<unit>", value))?;
+        let requests: u32 = requests
+            .trim()
+            .parse()
+            .map_err(|err| format!("invalid request count in `{}`: {}", value, err))?;
+        if requests == 0 {
+            return Err(format!(
+                "request count in `{}` must be greater than zero",
+                value
+            ));
+        }
+        let per = match unit.trim() {
+            "s" | "sec" | "second" => Duration::from_secs(1),
+            "m" | "min" | "minute" => Duration::from_secs(60),
+            "h" | "hour" => Duration::from_secs(60 * 60),
+            unit => return Err(format!("unknown unit `{}` in `{}`", unit, value)),
+        };
+        Ok(Rate { requests, per })
+    }
+}
+
+// Parse a comma separated list of rates, all of which must hold at once
+pub fn parse_rates(value: &str) -> Result<Vec<Rate>, String> {
+    value.split(',').map(str::parse).collect()
+}
+
+// Refills continuously up to `rate.requests` tokens per `rate.per`
+#[derive(Debug)]
+struct Bucket {
+    rate: Rate,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    fn new(rate: Rate, now: Instant) -> Self {
+        Bucket {
+            rate,
+            tokens: f64::from(rate.requests),
+            refilled_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let capacity = f64::from(self.rate.requests);
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * capacity / self.rate.per.as_secs_f64()).min(capacity);
+        self.refilled_at = now;
+    }
+
+    // Time until a whole token is available
+    fn wait(&self) -> Duration {
+        if self.tokens >= 1.0 {
+            return Duration::ZERO;
+        }
+        let per_token = self.rate.per.as_secs_f64() / f64::from(self.rate.requests);
+        Duration::from_secs_f64((1.0 - self.tokens) * per_token)
+    }
+}
+
+// Every bucket of a host, plus a pause requested by the server itself
+#[derive(Debug, Default)]
+struct HostLimit {
+    buckets: Vec<Bucket>,
+    paused_until: Option<Instant>,
+}
+
+// Token bucket rate limiter keyed by host, hosts without limits are never delayed
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    hosts: Mutex<HashMap<String, HostLimit>>,
+    // Set by the first `configure_once`
+    configured: OnceLock<()>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        RateLimiter::default()
+    }
+
+    // Limiter shared by every request of the process
+    pub fn global() -> &'static RateLimiter {
+        static LIMITER: OnceLock<RateLimiter> = OnceLock::new();
+        LIMITER.get_or_init(RateLimiter::new)
+    }
+
+    // Apply the configured limits to the Jikan, AniList, A-Z list, anime fetcher and
+    // hianime.to hosts
+    pub fn configure(&self, config: &Config) {
+        for (_, url, rates) in config.rate_limits() {
+            self.set_limit(url, rates);
+        }
+    }
+
+    // Apply the configured limits like `configure` unless they were applied before, so every
+    // client can configure the limiter without refilling the buckets of the others
+    pub fn configure_once(&self, config: &Config) {
+        self.configured.get_or_init(|| self.configure(config));
+    }
+
+    // Replace the limits of the host of `url`
+    pub fn set_limit(&self, url: &str, rates: &[Rate]) {
+        let Some(host) = host_key(url) else {
+            return;
+        };
+        let now = Instant::now();
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts.entry(host).or_default().buckets =
+            rates.iter().map(|rate| Bucket::new(*rate, now)).collect();
+    }
+
+    // Wait until a request to `url` is allowed and take a token from every bucket of its host
+    pub async fn acquire(&self, url: &str) {
+        let Some(host) = host_key(url) else {
+            return;
+        };
+
+        loop {
+            let wait = {
+                let now = Instant::now();
+                let mut hosts = self.hosts.lock().unwrap();
+                let Some(limit) = hosts.get_mut(&host) else {
+                    return;
+                };
+
+                let paused = limit
+                    .paused_until
+                    .map(|until| until.saturating_duration_since(now))
+                    .unwrap_or_default();
+                let mut wait = paused;
+                for bucket in &mut limit.buckets {
+                    bucket.refill(now);
+                    wait = wait.max(bucket.wait());
+                }
+
+                if wait.is_zero() {
+                    for bucket in &mut limit.buckets {
+                        bucket.tokens -= 1.0;
+                    }
+                    return;
+                }
+                wait
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    // Hold back every request to the host of `url` for `delay`, as asked by a 429
+    pub fn pause(&self, url: &str, delay: Duration) {
+        let Some(host) = host_key(url) else {
+            return;
+        };
+        let until = Instant::now() + delay;
+        let mut hosts = self.hosts.lock().unwrap();
+        let limit = hosts.entry(host).or_default();
+        if limit.paused_until.is_none_or(|paused| paused < until) {
+            limit.paused_until = Some(until);
+        }
+    }
+}
+
+// Host and port of a URL, so two services on one machine keep separate limits
+pub fn host_key(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    Some(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    })
+}
@@ -0,0 +1,75 @@
+// retry.rs
+
+use crate::config::Config;
+use crate::error::Error;
+use rand::Rng;
+use std::future::Future;
+use std::time::Duration;
+
+// How often a fetch failing with a transient error is sent again and how long to wait in
+// between. The wait doubles with every failure up to `max_delay` and is drawn at random
+// from the upper half of that, so jobs that failed together don't all retry together.
+// This is the only place requests are retried, 429s included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    // Attempts including the first one
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        RetryPolicy {
+            max_attempts: config.retry_max_attempts,
+            base_delay: config.retry_base_delay,
+            max_delay: config.retry_max_delay,
+        }
+    }
+
+    // Longest wait after the `failures`th failure
+    pub fn max_delay_after(&self, failures: u32) -> Duration {
+        let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+        self.base_delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    // Wait after the `failures`th failure, with jitter
+    pub fn delay_after(&self, failures: u32) -> Duration {
+        let half = self.max_delay_after(failures) / 2;
+        half + rand::thread_rng().gen_range(Duration::ZERO..=half)
+    }
+
+    // Run `fetch` until it succeeds, fails with an error that won't go away by retrying,
+    // or has been tried `max_attempts` times. Returns the last result
+    pub async fn run<T, F, Fut>(&self, mut fetch: F) -> Result<T, Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut failures = 0;
+        loop {
+            match fetch().await {
+                Err(e) if e.is_transient() && failures + 1 < self.max_attempts => {
+                    failures += 1;
+                    // A 429 is not sent again before the server asked for
+                    let delay = match &e {
+                        Error::RateLimited {
+                            retry_after: Some(retry_after),
+                            ..
+                        } => self.delay_after(failures).max(*retry_after),
+                        _ => self.delay_after(failures),
+                    };
+                    eprintln!(
+                        "{}, retrying in {:.1}s ({} of {} attempts failed)",
+                        e,
+                        delay.as_secs_f32(),
+                        failures,
+                        self.max_attempts
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+// scheduler.rs
+
+use crate::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{watch, Semaphore};
+use tokio::task::JoinSet;
+
+// Exit code used when a second Ctrl-C aborts the process, as shells do for SIGINT
+const EXIT_INTERRUPTED: i32 = 130;
+
+// Functions run before a second Ctrl-C exits the process, such as closing a sync run
+#[derive(Default)]
+struct AbortHooks(Mutex<Vec<Box<dyn FnOnce() + Send>>>);
+
+impl fmt::Debug for AbortHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AbortHooks")
+            .field(&self.0.lock().unwrap().len())
+            .finish()
+    }
+}
+
+// Runs jobs with a global limit on how many are in flight at once.
+// Clones share the limit, the cancellation flag and the abort hooks.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    semaphore: Arc<Semaphore>,
+    cancelled: Arc<watch::Sender<bool>>,
+    abort_hooks: Arc<AbortHooks>,
+}
+
+impl Scheduler {
+    pub fn new(concurrency: usize) -> Self {
+        let (cancelled, _) = watch::channel(false);
+        Scheduler {
+            semaphore: Arc::new(Semaphore::new(concurrency.max(1))),
+            cancelled: Arc::new(cancelled),
+            abort_hooks: Arc::default(),
+        }
+    }
+
+    // Stop starting new jobs, jobs already running are left to finish
+    pub fn cancel(&self) {
+        self.cancelled.send_replace(true);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.borrow()
+    }
+
+    // Run `hook` before a second Ctrl-C exits the process
+    pub fn on_abort(&self, hook: impl FnOnce() + Send + 'static) {
+        self.abort_hooks.0.lock().unwrap().push(Box::new(hook));
+    }
+
+    // Run the abort hooks, each of them once
+    pub fn abort(&self) {
+        let hooks = std::mem::take(&mut *self.abort_hooks.0.lock().unwrap());
+        for hook in hooks {
+            hook();
+        }
+    }
+
+    // Cancel on the first Ctrl-C and exit immediately on the second, after running the
+    // abort hooks
+    pub fn cancel_on_ctrl_c(&self) {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            eprintln!("Stopping after the running jobs finish, press Ctrl-C again to abort.");
+            scheduler.cancel();
+
+            if tokio::signal::ctrl_c().await.is_ok() {
+                scheduler.abort();
+                std::process::exit(EXIT_INTERRUPTED);
+            }
+        });
+    }
+
+    // Run `job` for every item in order, waiting for a free slot before starting each one.
+    // Returns the output of every job that was started, in completion order.
+    pub async fn run<T, R, F, Fut>(&self, items: Vec<T>, job: F) -> Result<Vec<R>, Error>
+    where
+        T: Send + 'static,
+        R: Send + 'static,
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = R> + Send + 'static,
+    {
+        let job = Arc::new(job);
+        let mut cancelled = self.cancelled.subscribe();
+        let mut running = JoinSet::new();
+
+        for item in items {
+            let permit = tokio::select! {
+                biased;
+                _ = cancelled.wait_for(|cancelled| *cancelled) => break,
+                permit = self.semaphore.clone().acquire_owned() => match permit {
+                    Ok(permit) => permit,
+                    Err(_) => break,
+                },
+            };
+
+            let job = job.clone();
+            running.spawn(async move {
+                let output = job(item).await;
+                drop(permit);
+                output
+            });
+        }
+
+        let mut outputs = Vec::new();
+        let mut join_error = None;
+        while let Some(result) = running.join_next().await {
+            match result {
+                Ok(output) => outputs.push(output),
+                Err(err) => join_error = Some(err),
+            }
+        }
+
+        match join_error {
+            Some(err) => Err(Error::Join(err)),
+            None => Ok(outputs),
+        }
+    }
+}
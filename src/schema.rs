@@ -15,21 +15,14 @@ diesel::table! {
         #[max_length = 200]
         image -> Varchar,
         #[max_length = 50]
-        category -> Varchar,
+        category -> Nullable<Varchar>,
         #[max_length = 50]
-        rating -> Varchar,
+        rating -> Nullable<Varchar>,
         #[max_length = 50]
         quality -> Varchar,
         #[max_length = 50]
-        duration -> Varchar,
-        #[max_length = 100]
-        premiered -> Varchar,
-        #[max_length = 100]
-        aired -> Varchar,
-        #[max_length = 50]
-        status -> Varchar,
-        #[max_length = 50]
-        mal_score -> Varchar,
+        status -> Nullable<Varchar>,
+        mal_score -> Nullable<Float4>,
         studios -> Text,
         producers -> Text,
         genres -> Text,
@@ -37,7 +30,79 @@ diesel::table! {
         dub_episodes -> Int4,
         total_episodes -> Int4,
         #[max_length = 50]
-        sub_or_dub -> Varchar,
+        sub_or_dub -> Nullable<Varchar>,
+        #[max_length = 50]
+        duration -> Nullable<Varchar>,
+        #[max_length = 100]
+        premiered -> Nullable<Varchar>,
+        #[max_length = 100]
+        aired -> Nullable<Varchar>,
+        duration_minutes -> Nullable<Int4>,
+        #[max_length = 50]
+        premiered_season -> Nullable<Varchar>,
+        premiered_year -> Nullable<Int4>,
+        aired_start -> Nullable<Date>,
+        aired_end -> Nullable<Date>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        last_fetched_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    anime_characters (anime_id, character_id) {
+        anime_id -> Int4,
+        character_id -> Int4,
+        #[max_length = 50]
+        role -> Varchar,
+    }
+}
+
+diesel::table! {
+    anime_enrichment (anime_id) {
+        anime_id -> Int4,
+        al_id -> Int4,
+        #[max_length = 20]
+        cover_color -> Nullable<Varchar>,
+        #[max_length = 500]
+        banner_image -> Nullable<Varchar>,
+        start_date -> Nullable<Date>,
+        end_date -> Nullable<Date>,
+        #[max_length = 20]
+        season -> Nullable<Varchar>,
+        season_year -> Nullable<Int4>,
+        popularity -> Nullable<Int4>,
+        average_score -> Nullable<Int4>,
+        fetched_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    anime_enrichment_tags (anime_id, name) {
+        anime_id -> Int4,
+        #[max_length = 100]
+        name -> Varchar,
+        rank -> Nullable<Int4>,
+    }
+}
+
+diesel::table! {
+    anime_genres (anime_id, genre_id) {
+        anime_id -> Int4,
+        genre_id -> Int4,
+    }
+}
+
+diesel::table! {
+    anime_history (id) {
+        id -> Int8,
+        anime_id -> Int4,
+        sync_run_id -> Nullable<Int8>,
+        #[max_length = 100]
+        field -> Varchar,
+        old_value -> Nullable<Text>,
+        new_value -> Nullable<Text>,
+        changed_at -> Timestamptz,
     }
 }
 
@@ -46,6 +111,25 @@ diesel::table! {
         id -> Int4,
         #[max_length = 500]
         anime_name -> Varchar,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        last_fetched_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    anime_producers (anime_id, producer_id) {
+        anime_id -> Int4,
+        producer_id -> Int4,
+    }
+}
+
+diesel::table! {
+    anime_relations (anime_id, related_mal_id) {
+        anime_id -> Int4,
+        related_mal_id -> Int4,
+        #[max_length = 50]
+        relation -> Varchar,
     }
 }
 
@@ -54,6 +138,43 @@ diesel::table! {
         anime_id -> Int4,
         staff_id -> Int4,
         positions -> Array<Nullable<Text>>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        last_fetched_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    anime_studios (anime_id, studio_id) {
+        anime_id -> Int4,
+        studio_id -> Int4,
+    }
+}
+
+diesel::table! {
+    characters (mal_id) {
+        mal_id -> Int4,
+        #[max_length = 255]
+        name -> Varchar,
+        #[max_length = 500]
+        mal_url -> Varchar,
+        #[max_length = 200]
+        image -> Varchar,
+    }
+}
+
+diesel::table! {
+    episode_history (id) {
+        id -> Int8,
+        #[max_length = 500]
+        episode_id -> Varchar,
+        anime_id -> Int4,
+        sync_run_id -> Nullable<Int8>,
+        #[max_length = 100]
+        field -> Varchar,
+        old_value -> Nullable<Text>,
+        new_value -> Nullable<Text>,
+        changed_at -> Timestamptz,
     }
 }
 
@@ -66,6 +187,25 @@ diesel::table! {
         title -> Varchar,
         is_filler -> Bool,
         anime_id -> Int4,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        last_fetched_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    genres (id) {
+        id -> Int4,
+        #[max_length = 255]
+        name -> Varchar,
+    }
+}
+
+diesel::table! {
+    producers (id) {
+        id -> Int4,
+        #[max_length = 255]
+        name -> Varchar,
     }
 }
 
@@ -79,17 +219,124 @@ diesel::table! {
         #[max_length = 200]
         image -> Varchar,
         positions -> Array<Nullable<Text>>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        last_fetched_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    studios (id) {
+        id -> Int4,
+        #[max_length = 255]
+        name -> Varchar,
+    }
+}
+
+diesel::table! {
+    sync_run_counts (sync_run_id, entity) {
+        sync_run_id -> Int8,
+        #[max_length = 50]
+        entity -> Varchar,
+        inserted -> Int8,
+        updated -> Int8,
+        unchanged -> Int8,
+        failed -> Int8,
+        deleted -> Int8,
+    }
+}
+
+diesel::table! {
+    sync_run_errors (id) {
+        id -> Int8,
+        sync_run_id -> Int8,
+        #[max_length = 50]
+        entity -> Varchar,
+        #[max_length = 500]
+        item_key -> Varchar,
+        message -> Text,
+    }
+}
+
+diesel::table! {
+    sync_runs (id) {
+        id -> Int8,
+        command -> Text,
+        #[max_length = 20]
+        status -> Varchar,
+        error -> Nullable<Text>,
+        started_at -> Timestamptz,
+        finished_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    sync_state (pipeline, item_key) {
+        #[max_length = 50]
+        pipeline -> Varchar,
+        #[max_length = 500]
+        item_key -> Varchar,
+        #[max_length = 20]
+        status -> Varchar,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    voice_actors (anime_id, character_id, staff_id) {
+        anime_id -> Int4,
+        character_id -> Int4,
+        staff_id -> Int4,
+        #[max_length = 50]
+        language -> Varchar,
     }
 }
 
+diesel::joinable!(anime_characters -> anime (anime_id));
+diesel::joinable!(anime_characters -> characters (character_id));
+diesel::joinable!(anime_enrichment -> anime (anime_id));
+diesel::joinable!(anime_enrichment_tags -> anime_enrichment (anime_id));
+diesel::joinable!(anime_genres -> anime (anime_id));
+diesel::joinable!(anime_genres -> genres (genre_id));
+diesel::joinable!(anime_history -> anime (anime_id));
+diesel::joinable!(anime_producers -> anime (anime_id));
+diesel::joinable!(anime_producers -> producers (producer_id));
+diesel::joinable!(anime_relations -> anime (anime_id));
 diesel::joinable!(anime_staff -> anime (anime_id));
 diesel::joinable!(anime_staff -> staff (staff_id));
+diesel::joinable!(anime_studios -> anime (anime_id));
+diesel::joinable!(anime_studios -> studios (studio_id));
+diesel::joinable!(episode_history -> anime (anime_id));
+diesel::joinable!(episode_history -> episodes (episode_id));
 diesel::joinable!(episodes -> anime (anime_id));
+diesel::joinable!(sync_run_counts -> sync_runs (sync_run_id));
+diesel::joinable!(sync_run_errors -> sync_runs (sync_run_id));
+diesel::joinable!(voice_actors -> staff (staff_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     anime,
+    anime_characters,
+    anime_enrichment,
+    anime_enrichment_tags,
+    anime_genres,
+    anime_history,
     anime_id,
+    anime_producers,
+    anime_relations,
     anime_staff,
+    anime_studios,
+    characters,
+    episode_history,
     episodes,
+    genres,
+    producers,
     staff,
+    studios,
+    sync_run_counts,
+    sync_run_errors,
+    sync_runs,
+    sync_state,
+    voice_actors,
 );
@@ -0,0 +1,282 @@
+// store.rs
+
+use crate::config::DatabaseConfig;
+use crate::db::{establish_pool, PgPool};
+use crate::error::Error;
+use crate::model::{
+    Anime, AnimeEnrichment, AnimeHistory, AnimeID, Episode, EpisodeHistory, Genre, Producer,
+    Studio, SyncRun, SyncRunCount, SyncRunError,
+};
+use crate::operations::anilist_ops::{load_enrichment, save_enrichment, AniListMedia};
+use crate::operations::anime_ops::{
+    add_new_anime, delete_anime_by_id, find_anime_ids_by_mal_id, insert_into_anime_id,
+    insert_into_anime_ids, load_all_anime, load_all_anime_ids, load_anime_by_id,
+    load_stale_anime_ids, load_stale_anime_names,
+};
+use crate::operations::character_ops::{save_characters_response, CharactersResponse};
+use crate::operations::episode_ops::add_new_episode;
+use crate::operations::history_ops::{load_anime_history, load_episode_history};
+use crate::operations::record_ops::{write_anime_record, AnimeRecord};
+use crate::operations::relation_ops::{
+    load_watch_order, save_relations_response, RelationsResponse,
+};
+use crate::operations::staff_ops::{save_staff_response, StaffResponse};
+use crate::operations::sync_run_ops::{
+    finish_sync_run, load_recent_sync_runs, load_sync_run_counts, load_sync_run_errors,
+    start_sync_run, RunCounts, RunRecorder, RunStatus,
+};
+use crate::operations::taxonomy_ops::{
+    anime_by_genre, anime_by_producer, anime_by_studio, load_anime_genres, load_anime_matching,
+    load_anime_producers, load_anime_studios, set_anime_taxonomy, AnimeFilter,
+};
+use chrono::TimeDelta;
+use diesel::Connection;
+
+// Postgres storage for anime, episodes and staff
+#[derive(Clone)]
+pub struct Store {
+    pool: PgPool,
+}
+
+impl Store {
+    pub fn new(pool: PgPool) -> Self {
+        Store { pool }
+    }
+
+    // Create a store backed by a pool built from the config
+    pub fn connect(config: &DatabaseConfig) -> Result<Self, Error> {
+        Ok(Store::new(establish_pool(config)?))
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    pub fn all_anime(&self) -> Result<Vec<Anime>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(load_all_anime(&mut connection)?)
+    }
+
+    pub fn anime(&self, anime_id: i32) -> Result<Option<Anime>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(load_anime_by_id(anime_id, &mut connection)?)
+    }
+
+    pub fn anime_ids_by_mal_id(&self, mal_id: i32) -> Result<Vec<i32>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(find_anime_ids_by_mal_id(mal_id, &mut connection)?)
+    }
+
+    // Names stored by the A-Z sync, used to fetch anime details
+    pub fn anime_names(&self) -> Result<Vec<String>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(load_all_anime_ids(&mut connection)?)
+    }
+
+    // IDs of the anime not fetched within `max_age`, oldest first
+    pub fn stale_anime_ids(&self, max_age: TimeDelta) -> Result<Vec<i32>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(load_stale_anime_ids(max_age, &mut connection)?)
+    }
+
+    // Names whose details were not fetched within `max_age`, oldest first
+    pub fn stale_anime_names(&self, max_age: TimeDelta) -> Result<Vec<String>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(load_stale_anime_names(max_age, &mut connection)?)
+    }
+
+    pub fn save_anime_id(&self, anime_id: &AnimeID) -> Result<(), Error> {
+        let mut connection = self.pool.get()?;
+        Ok(insert_into_anime_id(anime_id, &mut connection)?)
+    }
+
+    pub fn save_anime_ids(&self, anime_ids: &[AnimeID]) -> Result<usize, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(insert_into_anime_ids(anime_ids, &mut connection)?)
+    }
+
+    // Store an anime and link it to the genres, studios and producers it lists
+    pub fn save_anime(&self, anime: Anime) -> Result<(), Error> {
+        let mut connection = self.pool.get()?;
+        connection.transaction(|connection| {
+            add_new_anime(&anime, None, connection)?;
+            set_anime_taxonomy(&anime, connection)
+        })?;
+        Ok(())
+    }
+
+    pub fn save_episode(&self, episode: Episode) -> Result<(), Error> {
+        let mut connection = self.pool.get()?;
+        Ok(add_new_episode(episode, None, &mut connection)?)
+    }
+
+    // Store an anime with its episodes and staff in one transaction
+    pub fn save_anime_record(&self, record: AnimeRecord) -> Result<(), Error> {
+        let mut connection = self.pool.get()?;
+        write_anime_record(record, None, &mut connection)?;
+        Ok(())
+    }
+
+    // Store the staff of a MAL ID and link it to each of the given anime, all or nothing
+    pub fn save_staff(
+        &self,
+        response: &StaffResponse,
+        anime_ids: &[i32],
+    ) -> Result<RunCounts, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(save_staff_response(response, anime_ids, &mut connection)?)
+    }
+
+    // Store characters and voice actors and link them to the given anime, all or nothing
+    pub fn save_characters(
+        &self,
+        response: &CharactersResponse,
+        anime_ids: &[i32],
+    ) -> Result<RunCounts, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(save_characters_response(
+            response,
+            anime_ids,
+            &mut connection,
+        )?)
+    }
+
+    // Replace the relations of the given anime with the fetched ones
+    pub fn save_relations(
+        &self,
+        response: &RelationsResponse,
+        anime_ids: &[i32],
+    ) -> Result<RunCounts, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(save_relations_response(
+            response,
+            anime_ids,
+            &mut connection,
+        )?)
+    }
+
+    // Stored anime of the franchise of an anime, in watch order
+    pub fn watch_order(&self, anime_id: i32) -> Result<Vec<Anime>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(load_watch_order(anime_id, &mut connection)?)
+    }
+
+    // Store AniList fields for the given anime, replacing earlier ones
+    pub fn save_enrichment(&self, media: &AniListMedia, anime_ids: &[i32]) -> Result<(), Error> {
+        let mut connection = self.pool.get()?;
+        save_enrichment(media, anime_ids, &mut connection)?;
+        Ok(())
+    }
+
+    pub fn enrichment(&self, anime_id: i32) -> Result<Option<AnimeEnrichment>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(load_enrichment(anime_id, &mut connection)?)
+    }
+
+    // Anime matching every filter that is set
+    pub fn anime_matching(&self, filter: &AnimeFilter) -> Result<Vec<Anime>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(load_anime_matching(filter, &mut connection)?)
+    }
+
+    pub fn anime_by_genre(&self, genre: &str) -> Result<Vec<Anime>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(anime_by_genre(genre, &mut connection)?)
+    }
+
+    pub fn anime_by_studio(&self, studio: &str) -> Result<Vec<Anime>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(anime_by_studio(studio, &mut connection)?)
+    }
+
+    pub fn anime_by_producer(&self, producer: &str) -> Result<Vec<Anime>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(anime_by_producer(producer, &mut connection)?)
+    }
+
+    pub fn genres(&self, anime_id: i32) -> Result<Vec<Genre>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(load_anime_genres(anime_id, &mut connection)?)
+    }
+
+    pub fn studios(&self, anime_id: i32) -> Result<Vec<Studio>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(load_anime_studios(anime_id, &mut connection)?)
+    }
+
+    pub fn producers(&self, anime_id: i32) -> Result<Vec<Producer>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(load_anime_producers(anime_id, &mut connection)?)
+    }
+
+    // Changed fields of an anime, oldest first
+    pub fn history_for(&self, anime_id: i32) -> Result<Vec<AnimeHistory>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(load_anime_history(anime_id, &mut connection)?)
+    }
+
+    // Changed fields of the episodes of an anime, oldest first
+    pub fn episode_history_for(&self, anime_id: i32) -> Result<Vec<EpisodeHistory>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(load_episode_history(anime_id, &mut connection)?)
+    }
+
+    // Open a run in the sync run ledger for the given command line
+    pub fn start_sync_run(&self, command: &str) -> Result<RunRecorder, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(start_sync_run(command, &mut connection)?)
+    }
+
+    // Close a run with the outcome of its pipeline and everything it reported
+    pub fn finish_sync_run(
+        &self,
+        run: &RunRecorder,
+        result: &Result<(), Error>,
+        cancelled: bool,
+    ) -> Result<(), Error> {
+        let (status, error) = match result {
+            Err(e) => (RunStatus::Failed, Some(e.to_string())),
+            Ok(()) if cancelled => (RunStatus::Cancelled, None),
+            Ok(()) => (RunStatus::Succeeded, None),
+        };
+        let mut connection = self.pool.get()?;
+        Ok(finish_sync_run(
+            run,
+            status,
+            error.as_deref(),
+            &mut connection,
+        )?)
+    }
+
+    // Close a run as cancelled when the process is aborted before its pipeline returned
+    pub fn abort_sync_run(&self, run: &RunRecorder) -> Result<(), Error> {
+        let mut connection = self.pool.get()?;
+        Ok(finish_sync_run(
+            run,
+            RunStatus::Cancelled,
+            Some("aborted before the running jobs finished"),
+            &mut connection,
+        )?)
+    }
+
+    // The most recent sync runs, newest first
+    pub fn recent_sync_runs(&self, limit: i64) -> Result<Vec<SyncRun>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(load_recent_sync_runs(limit, &mut connection)?)
+    }
+
+    pub fn sync_run_counts(&self, sync_run_id: i64) -> Result<Vec<SyncRunCount>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(load_sync_run_counts(sync_run_id, &mut connection)?)
+    }
+
+    pub fn sync_run_errors(&self, sync_run_id: i64) -> Result<Vec<SyncRunError>, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(load_sync_run_errors(sync_run_id, &mut connection)?)
+    }
+
+    pub fn delete_anime(&self, anime_id: i32) -> Result<usize, Error> {
+        let mut connection = self.pool.get()?;
+        Ok(delete_anime_by_id(anime_id, &mut connection)?)
+    }
+}
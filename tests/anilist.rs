@@ -0,0 +1,130 @@
+// Tests for the AniList client against a local server replaying fixture responses
+
+use chrono::NaiveDate;
+use hianime_data_fetcher::operations::anilist_ops::{AniListClient, FuzzyDate};
+use hianime_data_fetcher::retry::RetryPolicy;
+use hianime_data_fetcher::Error;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// Serve `fixture` to a single request and send back the request body
+fn fixture_server(fixture: &'static str) -> (String, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+        sender.send(String::from_utf8(body).unwrap()).unwrap();
+
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            fixture.len(),
+            fixture
+        );
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+    });
+
+    (url, receiver)
+}
+
+fn retry_policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 1,
+        base_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(1),
+    }
+}
+
+#[tokio::test]
+async fn media_batch_is_parsed_into_enrichments() {
+    let (url, request) = fixture_server(include_str!("fixtures/anilist_media.json"));
+    let client = AniListClient::new(url, retry_policy());
+
+    let media = client.media(&[113415, 145064, 999999]).await.unwrap();
+
+    let request: serde_json::Value = serde_json::from_str(&request.recv().unwrap()).unwrap();
+    assert_eq!(
+        request["variables"]["ids"],
+        serde_json::json!([113415, 145064, 999999])
+    );
+    assert!(request["query"].as_str().unwrap().contains("id_in: $ids"));
+
+    assert_eq!(media.len(), 2);
+    let first = media[0].to_enrichment(7);
+    assert_eq!(first.anime_id, 7);
+    assert_eq!(first.al_id, 113415);
+    assert_eq!(first.cover_color.as_deref(), Some("#e4a15d"));
+    assert_eq!(first.start_date, NaiveDate::from_ymd_opt(2020, 10, 3));
+    assert_eq!(first.end_date, NaiveDate::from_ymd_opt(2021, 3, 27));
+    assert_eq!(first.season.as_deref(), Some("FALL"));
+    assert_eq!(first.season_year, Some(2020));
+    assert_eq!(first.popularity, Some(925018));
+    assert_eq!(first.average_score, Some(85));
+
+    let tags = media[0].to_tags(7);
+    assert_eq!(tags.len(), 3);
+    assert_eq!(tags[0].name, "Shounen");
+    assert_eq!(tags[0].rank, Some(94));
+
+    // An airing show has no end date yet
+    let second = media[1].to_enrichment(8);
+    assert_eq!(second.banner_image, None);
+    assert_eq!(second.end_date, None);
+}
+
+#[test]
+fn partial_dates_fall_back_to_the_first_of_the_month_or_year() {
+    let date = |year, month, day| FuzzyDate { year, month, day }.to_date();
+
+    assert_eq!(
+        date(Some(2020), Some(10), Some(3)),
+        NaiveDate::from_ymd_opt(2020, 10, 3)
+    );
+    assert_eq!(
+        date(Some(2020), Some(10), None),
+        NaiveDate::from_ymd_opt(2020, 10, 1)
+    );
+    assert_eq!(
+        date(Some(2020), None, None),
+        NaiveDate::from_ymd_opt(2020, 1, 1)
+    );
+    // A day without its month says nothing
+    assert_eq!(
+        date(Some(2020), None, Some(3)),
+        NaiveDate::from_ymd_opt(2020, 1, 1)
+    );
+    assert_eq!(date(None, Some(10), Some(3)), None);
+    assert_eq!(date(Some(2020), Some(2), Some(30)), None);
+}
+
+#[tokio::test]
+async fn graphql_errors_are_reported() {
+    let (url, _request) = fixture_server(include_str!("fixtures/anilist_error.json"));
+    let client = AniListClient::new(url, retry_policy());
+
+    match client.media(&[113415]).await {
+        Err(Error::Parse { message, .. }) => assert_eq!(message, "Too Many Requests."),
+        other => panic!("expected a parse error, got {:?}", other),
+    }
+}
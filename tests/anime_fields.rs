@@ -0,0 +1,20 @@
+// Tests for parsing the text fields of the anime details
+
+use hianime_data_fetcher::anime_fields::parse_duration_minutes;
+
+#[test]
+fn durations_are_parsed_into_minutes() {
+    assert_eq!(parse_duration_minutes("24m"), Some(24));
+    assert_eq!(parse_duration_minutes("1h 30m"), Some(90));
+    assert_eq!(parse_duration_minutes("2h"), Some(120));
+    assert_eq!(parse_duration_minutes(""), None);
+    assert_eq!(parse_duration_minutes("24 min"), None);
+}
+
+#[test]
+fn durations_too_long_for_an_i32_are_none() {
+    assert_eq!(parse_duration_minutes("35791394h"), Some(2147483640));
+    assert_eq!(parse_duration_minutes("35791395h"), None);
+    assert_eq!(parse_duration_minutes("35791394h 8m"), None);
+    assert_eq!(parse_duration_minutes("2147483647m 1m"), None);
+}
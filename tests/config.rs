@@ -0,0 +1,80 @@
+// Tests for loading the config file, in particular the rate limits of shared hosts
+
+use hianime_data_fetcher::config::{Config, DatabaseConfig};
+use hianime_data_fetcher::rate_limit::parse_rates;
+use hianime_data_fetcher::Error;
+use std::fs;
+use std::path::Path;
+
+// The settings that must be set, with the A-Z list and the anime fetcher on one host
+const REQUIRED: &str = r#"
+database_url = "postgres://postgres@localhost/hianime"
+atozlist_url = "https://api.test/aniwatch/az-list?page="
+anime_fetcher_url = "https://api.test/anime"
+http_url = "https://proxies.test/http.txt"
+"#;
+
+// Load a config file holding `contents`
+fn load(name: &str, contents: &str) -> Result<Config, Error> {
+    with_file(name, contents, Config::load)
+}
+
+// Call `load` with the path of a config file holding `contents`
+fn with_file<T>(name: &str, contents: &str, load: impl FnOnce(Option<&Path>) -> T) -> T {
+    let path = std::env::temp_dir().join(format!("{}-{}.toml", name, std::process::id()));
+    fs::write(&path, contents).unwrap();
+    let config = load(Some(&path));
+    fs::remove_file(&path).unwrap();
+    config
+}
+
+#[test]
+fn required_settings_are_enough() {
+    let config = load("required", REQUIRED).unwrap();
+
+    // Both defaults of the shared host hold
+    let rates = parse_rates("5/s,10/s").unwrap();
+    assert_eq!(config.atoz_rate_limit, rates);
+    assert_eq!(config.anime_fetcher_rate_limit, rates);
+}
+
+#[test]
+fn a_set_limit_applies_to_the_whole_host() {
+    let config = load(
+        "one-limit",
+        &format!("{}anime_fetcher_rate_limit = \"2/s\"\n", REQUIRED),
+    )
+    .unwrap();
+
+    let rates = parse_rates("2/s").unwrap();
+    assert_eq!(config.atoz_rate_limit, rates);
+    assert_eq!(config.anime_fetcher_rate_limit, rates);
+}
+
+#[test]
+fn set_limits_of_one_host_must_agree() {
+    let result = load(
+        "two-limits",
+        &format!(
+            "{}atoz_rate_limit = \"5/s\"\nanime_fetcher_rate_limit = \"10/s\"\n",
+            REQUIRED
+        ),
+    );
+
+    let Err(Error::Config(problems)) = result else {
+        panic!("conflicting limits were accepted");
+    };
+    assert_eq!(
+        problems,
+        ["ATOZ_RATE_LIMIT and ANIME_FETCHER_RATE_LIMIT limit the same host api.test differently"]
+    );
+}
+
+#[test]
+fn the_database_settings_load_without_the_fetch_settings() {
+    let contents = "database_url = \"postgres://postgres@localhost/hianime\"\n";
+    assert!(load("database", contents).is_err());
+
+    let database = with_file("database", contents, DatabaseConfig::load).unwrap();
+    assert_eq!(database.url, "postgres://postgres@localhost/hianime");
+}
@@ -0,0 +1,141 @@
+// Tests for the rate limits, their settings and the Retry-After delays of 429s
+
+use chrono::{TimeZone, Utc};
+use hianime_data_fetcher::config::Config;
+use hianime_data_fetcher::http::parse_retry_after;
+use hianime_data_fetcher::rate_limit::{parse_rates, Rate, RateLimiter};
+use std::fs;
+use std::time::Duration;
+use tokio::time::Instant;
+
+const URL: &str = "http://api.test:3001/anime/x";
+
+fn rate(requests: u32, secs: u64) -> Rate {
+    Rate {
+        requests,
+        per: Duration::from_secs(secs),
+    }
+}
+
+#[test]
+fn rates_are_parsed() {
+    assert_eq!("3/s".parse(), Ok(rate(3, 1)));
+    assert_eq!(" 60 / min ".parse(), Ok(rate(60, 60)));
+    assert_eq!("1000/h".parse(), Ok(rate(1000, 3600)));
+    assert_eq!(
+        parse_rates("3/s,60/min"),
+        Ok(vec![rate(3, 1), rate(60, 60)])
+    );
+
+    for invalid in ["3", "0/s", "x/s", "3/day", "-1/s"] {
+        assert!(invalid.parse::<Rate>().is_err(), "{}", invalid);
+    }
+    assert!(parse_rates("3/s,").is_err());
+}
+
+#[tokio::test(start_paused = true)]
+async fn requests_past_the_limit_wait_for_a_token() {
+    let limiter = RateLimiter::new();
+    limiter.set_limit(URL, &[rate(2, 1)]);
+    let started = Instant::now();
+
+    limiter.acquire(URL).await;
+    limiter.acquire(URL).await;
+    assert_eq!(started.elapsed(), Duration::ZERO);
+
+    // One token is refilled every 500ms
+    limiter.acquire(URL).await;
+    assert_eq!(started.elapsed(), Duration::from_millis(500));
+    limiter.acquire(URL).await;
+    assert_eq!(started.elapsed(), Duration::from_millis(1000));
+}
+
+#[tokio::test(start_paused = true)]
+async fn every_rate_of_a_host_must_hold() {
+    let limiter = RateLimiter::new();
+    limiter.set_limit(URL, &[rate(10, 1), rate(2, 60)]);
+    let started = Instant::now();
+
+    limiter.acquire(URL).await;
+    limiter.acquire(URL).await;
+    limiter.acquire(URL).await;
+
+    assert_eq!(started.elapsed(), Duration::from_secs(30));
+}
+
+#[tokio::test(start_paused = true)]
+async fn hosts_are_limited_separately() {
+    let limiter = RateLimiter::new();
+    limiter.set_limit(URL, &[rate(1, 1)]);
+    let started = Instant::now();
+
+    limiter.acquire(URL).await;
+    // Another port on the same machine, and a host without limits
+    limiter.acquire("http://api.test:3002/anime/x").await;
+    limiter.acquire("http://other.test/anime/x").await;
+    limiter.acquire("http://other.test/anime/x").await;
+
+    assert_eq!(started.elapsed(), Duration::ZERO);
+}
+
+#[tokio::test(start_paused = true)]
+async fn only_the_first_configuration_is_applied() {
+    let path = std::env::temp_dir().join(format!("limits-{}.toml", std::process::id()));
+    fs::write(
+        &path,
+        r#"
+database_url = "postgres://postgres@localhost/hianime"
+atozlist_url = "http://site.invalid/az-list?page="
+anime_fetcher_url = "http://site.invalid/anime"
+proxy_mode = "direct"
+jikan_api_url = "http://api.test:3001/v4"
+jikan_rate_limit = "1/s"
+"#,
+    )
+    .unwrap();
+    let config = Config::load(Some(&path)).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    let limiter = RateLimiter::new();
+    let started = Instant::now();
+    limiter.configure_once(&config);
+    limiter.acquire(URL).await;
+
+    // Configuring again must not refill the bucket
+    limiter.configure_once(&config);
+    limiter.acquire(URL).await;
+    assert_eq!(started.elapsed(), Duration::from_secs(1));
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_pause_holds_back_the_host() {
+    let limiter = RateLimiter::new();
+    let started = Instant::now();
+
+    limiter.pause(URL, Duration::from_secs(5));
+    // A shorter pause does not cut the longer one short
+    limiter.pause(URL, Duration::from_secs(1));
+    limiter.acquire(URL).await;
+
+    assert_eq!(started.elapsed(), Duration::from_secs(5));
+}
+
+#[test]
+fn retry_after_is_read_as_seconds_or_http_date() {
+    let now = Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap();
+
+    assert_eq!(
+        parse_retry_after("120", now),
+        Some(Duration::from_secs(120))
+    );
+    assert_eq!(
+        parse_retry_after("Sun, 18 Oct 2026 12:00:30 GMT", now),
+        Some(Duration::from_secs(30))
+    );
+    // A date in the past means retry now
+    assert_eq!(
+        parse_retry_after("Sun, 18 Oct 2026 11:00:00 GMT", now),
+        Some(Duration::ZERO)
+    );
+    assert_eq!(parse_retry_after("soon", now), None);
+}
@@ -0,0 +1,149 @@
+// Tests for the retry policy shared by the fetchers
+
+use hianime_data_fetcher::retry::RetryPolicy;
+use hianime_data_fetcher::Error;
+use reqwest::StatusCode;
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+fn policy(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        base_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(4),
+    }
+}
+
+fn server_error() -> Error {
+    Error::Http {
+        url: String::from("http://localhost/anime/x"),
+        status: StatusCode::BAD_GATEWAY,
+    }
+}
+
+#[test]
+fn errors_are_classified() {
+    let url = String::from("http://localhost/anime/x");
+    assert!(server_error().is_transient());
+    assert!(Error::RateLimited {
+        url: url.clone(),
+        retry_after: None,
+    }
+    .is_transient());
+    assert!(Error::Http {
+        url: url.clone(),
+        status: StatusCode::REQUEST_TIMEOUT,
+    }
+    .is_transient());
+
+    assert!(!Error::NotFound { url: url.clone() }.is_transient());
+
+    // Errors tied to an anime are classified by what went wrong
+    let error = server_error().for_anime("one-piece-100");
+    assert!(error.is_transient());
+    assert_eq!(error.anime(), Some("one-piece-100"));
+    assert_eq!(error.url(), Some(url.as_str()));
+    assert!(!Error::Http {
+        url: url.clone(),
+        status: StatusCode::FORBIDDEN,
+    }
+    .is_transient());
+    assert!(!Error::Parse {
+        url,
+        message: String::from("missing field `id`"),
+    }
+    .is_transient());
+}
+
+#[test]
+fn delays_double_up_to_the_maximum() {
+    let policy = RetryPolicy {
+        max_attempts: 10,
+        base_delay: Duration::from_millis(500),
+        max_delay: Duration::from_secs(3),
+    };
+    let ceilings: Vec<_> = (1..=5).map(|n| policy.max_delay_after(n)).collect();
+    assert_eq!(
+        ceilings,
+        [500, 1000, 2000, 3000, 3000].map(Duration::from_millis)
+    );
+
+    for failures in 1..=5 {
+        let delay = policy.delay_after(failures);
+        let ceiling = policy.max_delay_after(failures);
+        assert!(delay >= ceiling / 2 && delay <= ceiling, "{:?}", delay);
+    }
+}
+
+#[tokio::test]
+async fn transient_errors_are_retried_until_success() {
+    let attempts = Cell::new(0);
+
+    let result = policy(5)
+        .run(|| async {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() < 3 {
+                Err(server_error())
+            } else {
+                Ok(attempts.get())
+            }
+        })
+        .await;
+
+    assert_eq!(result.unwrap(), 3);
+}
+
+#[tokio::test]
+async fn retries_stop_after_the_last_attempt() {
+    let attempts = Cell::new(0);
+
+    let result: Result<(), Error> = policy(4)
+        .run(|| async {
+            attempts.set(attempts.get() + 1);
+            Err(server_error())
+        })
+        .await;
+
+    assert!(matches!(result, Err(Error::Http { .. })));
+    assert_eq!(attempts.get(), 4);
+}
+
+#[tokio::test]
+async fn permanent_errors_fail_fast() {
+    let attempts = Cell::new(0);
+
+    let result: Result<(), Error> = policy(5)
+        .run(|| async {
+            attempts.set(attempts.get() + 1);
+            Err(Error::NotFound {
+                url: String::from("http://localhost/anime/bad-one"),
+            })
+        })
+        .await;
+
+    assert!(matches!(result, Err(Error::NotFound { .. })));
+    assert_eq!(attempts.get(), 1);
+}
+
+#[tokio::test]
+async fn rate_limited_fetches_wait_for_retry_after() {
+    let attempts = Cell::new(0);
+    let started = Instant::now();
+
+    let result = policy(2)
+        .run(|| async {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() == 1 {
+                Err(Error::RateLimited {
+                    url: String::from("http://localhost/anime/x"),
+                    retry_after: Some(Duration::from_millis(200)),
+                })
+            } else {
+                Ok(())
+            }
+        })
+        .await;
+
+    assert!(result.is_ok());
+    assert!(started.elapsed() >= Duration::from_millis(200));
+}
@@ -0,0 +1,22 @@
+// Tests for the job scheduler
+
+use hianime_data_fetcher::scheduler::Scheduler;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[test]
+fn abort_hooks_run_once() {
+    let scheduler = Scheduler::new(1);
+    let calls = Arc::new(AtomicUsize::new(0));
+    for _ in 0..2 {
+        let calls = calls.clone();
+        scheduler.clone().on_abort(move || {
+            calls.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+
+    scheduler.abort();
+    scheduler.abort();
+
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}